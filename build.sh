@@ -20,4 +20,9 @@ cp target/release/weoo target/release/weoo_$DEVv$VERSION
 cp target/x86_64-pc-windows-gnu/release/weoo.exe target/x86_64-pc-windows-gnu/release/weoo_$DEVv$VERSION.exe
 
 # Zip everything
-zip -j "weoo_$DEVv$VERSION.zip" target/release/weoo_$DEVv$VERSION target/x86_64-pc-windows-gnu/release/weoo_$DEVv$VERSION.exe Database.json CustomPoi.json
+zip -j "weoo_$DEVv$VERSION.zip" target/release/weoo_$DEVv$VERSION target/x86_64-pc-windows-gnu/release/weoo_$DEVv$VERSION.exe Database.json CustomPoi.json QtDrives.json
+# Custom POIs, one file each
+if [ -d pois ];
+then
+	zip -r "weoo_$DEVv$VERSION.zip" pois
+fi
@@ -0,0 +1,58 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+/// Rotation calibration workflow: captures taken at a known POI of a container
+#[derive(Debug, Default)]
+pub struct Calibration {
+    pub widget_open: bool,
+    pub container: String,
+    pub poi: String,
+    pub samples: Vec<ProcessedPosition>,
+    pub fit_rotation_speed: bool,
+    pub result: Option<CalibrationResult>,
+}
+
+impl Calibration {
+    pub fn solve(&mut self, database: &Database) {
+        self.result = database.get(&self.container).and_then(|container| {
+            container.poi.get(&self.poi).and_then(|poi| {
+                calibrate_rotation(container, poi, &self.samples, self.fit_rotation_speed)
+            })
+        });
+    }
+
+    /// Apply solved rotation to database and save it to DatabaseOverride.json
+    pub fn apply(&mut self, database: &mut Database) {
+        if let (Some(result), Some(container)) = (self.result, database.get_mut(&self.container)) {
+            container.rotation_adjust = result.rotation_adjust;
+            container.rotation_speed = result.rotation_speed;
+
+            // Keep previous override of rotation speed if not fitted
+            let mut container_override = load_database_override()
+                .remove(&self.container)
+                .unwrap_or_default();
+            container_override.rotation_adjust = Some(result.rotation_adjust);
+            if self.fit_rotation_speed {
+                container_override.rotation_speed = Some(result.rotation_speed);
+            }
+            save_database_override(&self.container, container_override);
+            self.result = None;
+        }
+    }
+}
@@ -0,0 +1,100 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use std::collections::HashSet;
+
+/// Merge of near identical custom POIs
+#[derive(Debug)]
+pub struct Dedupe {
+    pub widget_open: bool,
+    /// POIs closer than this are the same place, in km
+    pub threshold: f64,
+    pub proposals: Vec<MergeProposal>,
+    /// Proposals to apply, same order
+    pub accepted: Vec<bool>,
+    /// Outcome of last merge
+    pub summary: String,
+}
+
+impl Default for Dedupe {
+    fn default() -> Self {
+        Self {
+            widget_open: false,
+            threshold: 0.5,
+            proposals: Vec::new(),
+            accepted: Vec::new(),
+            summary: String::new(),
+        }
+    }
+}
+
+impl Dedupe {
+    pub fn search(&mut self, database: &Database, custom_pois: &CustomPoiStore) {
+        let custom: HashSet<PoiKey> = custom_pois.pois().map(poi_key).collect();
+        self.proposals = propose_merges(database, &custom, self.threshold);
+        self.accepted = vec![true; self.proposals.len()];
+        self.summary = String::new();
+    }
+}
+
+impl MyEguiApp {
+    /// Apply accepted merges as a single edit
+    pub fn merge_pois(&mut self) {
+        let mut before = Vec::new();
+        let mut before_custom = Vec::new();
+        let mut after = Vec::new();
+        let mut kept = Vec::new();
+        for (proposal, _) in self
+            .dedupe
+            .proposals
+            .iter()
+            .zip(&self.dedupe.accepted)
+            .filter(|(_, accepted)| **accepted)
+        {
+            for poi in proposal.replaced() {
+                before.push(poi.clone());
+                if let Some(custom_poi) = self.custom_pois.get(&poi.container, &poi.name) {
+                    before_custom.push(custom_poi.clone());
+                }
+            }
+            // Imported canonical is kept as is
+            after.extend(proposal.merged(&self.database));
+            kept.push(proposal.canonical.clone());
+        }
+        if kept.is_empty() {
+            return;
+        }
+
+        self.dedupe.summary = format!(
+            "Merged {} custom POIs into {}: {}",
+            before.len(),
+            kept.len(),
+            kept.join(", ")
+        );
+        let edit = Edit::PoisMerged {
+            before,
+            before_custom,
+            after,
+        };
+        self.apply_edit(&edit);
+        self.edit_history.push(edit);
+        self.dedupe.proposals = Vec::new();
+        self.dedupe.accepted = Vec::new();
+    }
+}
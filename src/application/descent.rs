@@ -0,0 +1,93 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use chrono::{DateTime, Utc};
+
+/// Descent toward a target, with glide path fixed at first capture
+#[derive(Debug, Default)]
+pub struct Descent {
+    pub widget_open: bool,
+    /// Name of followed target
+    pub target: String,
+    pub guidance: Option<DescentGuidance>,
+    /// Glide path start, horizontal distance and altitude in km
+    pub start: Option<[f64; 2]>,
+    /// Target altitude in km
+    pub end_altitude: f64,
+    /// Captures since start, horizontal distance and altitude in km
+    pub track: Vec<[f64; 2]>,
+    last_capture: Option<DateTime<Utc>>,
+}
+
+impl Descent {
+    /// New glide path from next capture
+    pub fn restart(&mut self) {
+        self.start = None;
+        self.track = Vec::new();
+        self.last_capture = None;
+    }
+
+    pub fn update(
+        &mut self,
+        targets: &Targets,
+        database: &Database,
+        live_point: Option<&ProcessedPosition>,
+    ) {
+        let target = targets
+            .iter()
+            .find(|t| t.current_point.name == self.target)
+            .filter(|_| self.widget_open);
+        let (Some(target), Some(live_point)) = (target, live_point) else {
+            self.guidance = None;
+            return;
+        };
+        self.guidance = descent_guidance(
+            live_point,
+            Destination::Position(&target.current_point),
+            database,
+        );
+        let Some(guidance) = self.guidance else {
+            return;
+        };
+
+        // Track only new captures
+        let timestamp = live_point.space_time_position.timestamp;
+        if self.last_capture == Some(timestamp) {
+            return;
+        }
+        self.last_capture = Some(timestamp);
+        let point = [guidance.horizontal_distance, guidance.altitude];
+        if self.start.is_none() {
+            self.start = Some(point);
+            self.end_altitude = guidance.altitude - guidance.altitude_to_lose;
+        }
+        self.track.push(point);
+    }
+
+    /// Altitude on glide path at horizontal distance, in km
+    pub fn planned_altitude(&self, horizontal_distance: f64) -> f64 {
+        match self.start {
+            Some([distance, altitude]) if distance > 0.0 => {
+                self.end_altitude
+                    + (altitude - self.end_altitude) * (horizontal_distance / distance).min(1.0)
+            }
+            _ => f64::NAN,
+        }
+    }
+}
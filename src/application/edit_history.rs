@@ -0,0 +1,353 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+/// A reversible user edit on paths, targets or custom POI
+#[derive(Debug, Clone)]
+pub enum Edit {
+    /// All points of a path replaced by path tools, points captured since are kept
+    PathHistory {
+        name: String,
+        before: Vec<ProcessedPosition>,
+        after: Vec<ProcessedPosition>,
+    },
+    /// All points of a path removed, undone ahead of points captured since
+    PathCleared {
+        name: String,
+        points: Vec<ProcessedPosition>,
+    },
+    /// Point inserted in a path at index, undone by its name
+    PathPointAdded {
+        name: String,
+        index: usize,
+        point: ProcessedPosition,
+    },
+    /// Point removed from a path at index
+    PathPointRemoved {
+        name: String,
+        index: usize,
+        point: ProcessedPosition,
+    },
+    /// Point of a path moved from an index to another
+    PathPointMoved {
+        name: String,
+        point: String,
+        from: usize,
+        to: usize,
+    },
+    PathAdded(Path),
+    PathRemoved(Path),
+    TargetAdded(usize, Target),
+    TargetRemoved(usize, Target),
+    TargetsReordered {
+        before: Vec<Target>,
+        after: Vec<Target>,
+    },
+    /// Custom POI saved, with what was there before in database and custom POI store
+    PoiSaved {
+        before: Option<Box<Poi>>,
+        before_custom: Option<Box<Poi>>,
+        after: Box<Poi>,
+    },
+    /// Near identical POIs merged, with database and custom POI store entries they replaced
+    PoisMerged {
+        before: Vec<Poi>,
+        before_custom: Vec<Poi>,
+        after: Vec<Poi>,
+    },
+}
+
+impl Edit {
+    pub fn description(&self) -> String {
+        match self {
+            Edit::PathHistory {
+                name,
+                before,
+                after,
+            } => {
+                if after.is_empty() {
+                    format!("Clear path {name}")
+                } else if after.len() > before.len() {
+                    format!("Add point to {name}")
+                } else if after.len() < before.len() {
+                    format!("Delete point of {name}")
+                } else {
+                    format!("Move point of {name}")
+                }
+            }
+            Edit::PathCleared { name, .. } => format!("Clear path {name}"),
+            Edit::PathPointAdded { name, .. } => format!("Add point to {name}"),
+            Edit::PathPointRemoved { name, .. } => format!("Delete point of {name}"),
+            Edit::PathPointMoved { name, .. } => format!("Move point of {name}"),
+            Edit::PathAdded(path) => format!("Add path {}", path.name),
+            Edit::PathRemoved(path) => format!("Delete path {}", path.name),
+            Edit::TargetAdded(_, target) => format!("Add target {}", target.current_point.name),
+            Edit::TargetRemoved(_, target) => {
+                format!("Delete target {}", target.current_point.name)
+            }
+            Edit::TargetsReordered { .. } => "Reorder targets".to_string(),
+            Edit::PoiSaved { after, .. } => format!("Save POI {}", after.name),
+            Edit::PoisMerged { before, .. } => format!("Merge {} custom POIs", before.len()),
+        }
+    }
+}
+
+/// Undo & redo stacks of edits
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    pub undo: Vec<Edit>,
+    pub redo: Vec<Edit>,
+    pub widget_open: bool,
+}
+
+impl EditHistory {
+    /// Record a new edit, it invalidate all redo
+    pub fn push(&mut self, edit: Edit) {
+        self.undo.push(edit);
+        self.redo = Vec::new();
+    }
+
+    /// Record a change of points of a path
+    pub fn push_path(
+        &mut self,
+        name: &str,
+        before: Vec<ProcessedPosition>,
+        after: &[ProcessedPosition],
+    ) {
+        self.push(Edit::PathHistory {
+            name: name.to_string(),
+            before,
+            after: after.to_vec(),
+        });
+    }
+}
+
+impl MyEguiApp {
+    pub fn undo(&mut self) {
+        if let Some(edit) = self.edit_history.undo.pop() {
+            self.revert_edit(&edit);
+            self.edit_history.redo.push(edit);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(edit) = self.edit_history.redo.pop() {
+            self.apply_edit(&edit);
+            self.edit_history.undo.push(edit);
+        }
+    }
+
+    /// Do an edit, without recording it
+    pub fn apply_edit(&mut self, edit: &Edit) {
+        match edit {
+            Edit::PathHistory {
+                name,
+                before,
+                after,
+            } => self.replace_path_history(name, before, after),
+            Edit::PathCleared { name, points } => self.replace_path_history(name, points, &[]),
+            Edit::PathPointAdded { name, index, point } => {
+                self.insert_path_point(name, *index, point)
+            }
+            Edit::PathPointRemoved { name, index, point } => {
+                self.remove_path_point(name, *index, &point.name);
+            }
+            Edit::PathPointMoved {
+                name,
+                point,
+                from,
+                to,
+            } => self.move_path_point(name, point, *from, *to),
+            Edit::PathAdded(path) => {
+                self.global_paths.insert(path.name.clone(), path.clone());
+            }
+            Edit::PathRemoved(path) => {
+                self.global_paths.remove(&path.name);
+            }
+            Edit::TargetAdded(index, target) => {
+                let index = (*index).min(self.global_targets.len());
+                self.global_targets.insert(index, target.clone());
+            }
+            Edit::TargetRemoved(index, _) => {
+                if *index < self.global_targets.len() {
+                    self.global_targets.remove(*index);
+                }
+            }
+            Edit::TargetsReordered { after, .. } => self.global_targets = after.clone(),
+            Edit::PoiSaved { after, .. } => {
+                if let Some(container) = self.database.get_mut(&after.container) {
+                    container.poi.insert(after.name.clone(), *after.clone());
+                }
+                self.custom_pois.save(after);
+            }
+            Edit::PoisMerged {
+                before,
+                before_custom,
+                after,
+            } => {
+                for poi in before {
+                    if let Some(container) = self.database.get_mut(&poi.container) {
+                        container.poi.remove(&poi.name);
+                    }
+                }
+                for poi in before_custom {
+                    self.custom_pois.delete(&poi.container, &poi.name);
+                }
+                for poi in after {
+                    if let Some(container) = self.database.get_mut(&poi.container) {
+                        container.poi.insert(poi.name.clone(), poi.clone());
+                    }
+                    self.custom_pois.save(poi);
+                }
+            }
+        }
+    }
+
+    fn revert_edit(&mut self, edit: &Edit) {
+        match edit {
+            Edit::PathHistory {
+                name,
+                before,
+                after,
+            } => self.replace_path_history(name, after, before),
+            Edit::PathCleared { name, points } => self.replace_path_history(name, &[], points),
+            Edit::PathPointAdded { name, index, point } => {
+                self.remove_path_point(name, *index, &point.name);
+            }
+            Edit::PathPointRemoved { name, index, point } => {
+                self.insert_path_point(name, *index, point)
+            }
+            Edit::PathPointMoved {
+                name,
+                point,
+                from,
+                to,
+            } => self.move_path_point(name, point, *to, *from),
+            Edit::PathAdded(path) => self.apply_edit(&Edit::PathRemoved(path.clone())),
+            Edit::PathRemoved(path) => self.apply_edit(&Edit::PathAdded(path.clone())),
+            Edit::TargetAdded(index, target) => {
+                self.apply_edit(&Edit::TargetRemoved(*index, target.clone()))
+            }
+            Edit::TargetRemoved(index, target) => {
+                self.apply_edit(&Edit::TargetAdded(*index, target.clone()))
+            }
+            Edit::TargetsReordered { before, .. } => self.global_targets = before.clone(),
+            Edit::PoiSaved {
+                before,
+                before_custom,
+                after,
+            } => {
+                if let Some(container) = self.database.get_mut(&after.container) {
+                    container.poi.remove(&after.name);
+                    if let Some(before) = before {
+                        container.poi.insert(before.name.clone(), *before.clone());
+                    }
+                }
+                match before_custom {
+                    Some(before_custom) => self.custom_pois.save(before_custom),
+                    None => self.custom_pois.delete(&after.container, &after.name),
+                }
+            }
+            Edit::PoisMerged {
+                before,
+                before_custom,
+                after,
+            } => {
+                for poi in after {
+                    if let Some(container) = self.database.get_mut(&poi.container) {
+                        container.poi.remove(&poi.name);
+                    }
+                    self.custom_pois.delete(&poi.container, &poi.name);
+                }
+                for poi in before {
+                    if let Some(container) = self.database.get_mut(&poi.container) {
+                        container.poi.insert(poi.name.clone(), poi.clone());
+                    }
+                }
+                for poi in before_custom {
+                    self.custom_pois.save(poi);
+                }
+            }
+        }
+    }
+
+    /// Replace points `old` of a path by `new`, points captured since stay after them
+    fn replace_path_history(
+        &mut self,
+        name: &str,
+        old: &[ProcessedPosition],
+        new: &[ProcessedPosition],
+    ) {
+        let Some(path) = self.global_paths.get_mut(name) else {
+            return;
+        };
+        let mut old: Vec<&str> = old.iter().map(|p| p.name.as_str()).collect();
+        let mut history = new.to_vec();
+        let mut current_index = history.len();
+        for (i, point) in std::mem::take(&mut path.history).into_iter().enumerate() {
+            if let Some(j) = old.iter().position(|name| *name == point.name) {
+                old.swap_remove(j);
+                continue;
+            }
+            if i < path.current_index {
+                current_index += 1;
+            }
+            history.push(point);
+        }
+        path.history = history;
+        path.current_index = current_index;
+    }
+
+    fn insert_path_point(&mut self, name: &str, index: usize, point: &ProcessedPosition) {
+        if let Some(path) = self.global_paths.get_mut(name) {
+            let index = index.min(path.history.len());
+            path.history.insert(index, point.clone());
+        }
+    }
+
+    /// Remove point with this name, the nearest to index if several, points added since may
+    /// have shifted it
+    fn remove_path_point(
+        &mut self,
+        name: &str,
+        index: usize,
+        point_name: &str,
+    ) -> Option<ProcessedPosition> {
+        let path = self.global_paths.get_mut(name)?;
+        let i = find_point(&path.history, point_name, index)?;
+        let point = path.history.remove(i);
+        path.current_index = path.current_index.min(path.history.len());
+        Some(point)
+    }
+
+    fn move_path_point(&mut self, name: &str, point_name: &str, from: usize, to: usize) {
+        if let Some(point) = self.remove_path_point(name, from, point_name) {
+            self.insert_path_point(name, to, &point);
+        }
+    }
+}
+
+/// Index of point with this name, the nearest to index if several
+fn find_point(history: &[ProcessedPosition], point_name: &str, index: usize) -> Option<usize> {
+    history
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.name == point_name)
+        .min_by_key(|(i, _)| i.abs_diff(index))
+        .map(|(i, _)| i)
+}
@@ -17,19 +17,27 @@
 
 use crate::prelude::*;
 
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 use egui::Color32;
 use egui_plot::MarkerShape;
-use std::{
-    collections::{BTreeMap, HashMap},
-    f64::{consts::PI, NAN},
-};
+use std::collections::{BTreeMap, HashMap};
 
 pub type Paths = HashMap<String, Path>;
 pub type Targets = Vec<Target>;
 pub type Database = BTreeMap<String, Container>;
 
-#[derive(Debug)]
+/// Name not already used in paths, numbered if needed
+pub fn unique_path_name(paths: &Paths, name: &str) -> String {
+    let mut unique = name.to_string();
+    let mut i = 1;
+    while paths.contains_key(&unique) {
+        i += 1;
+        unique = format!("{name} #{i}");
+    }
+    unique
+}
+
+#[derive(Debug, Clone)]
 pub struct Target {
     // Display on Map info
     pub map_color: Color32,
@@ -39,8 +47,27 @@ pub struct Target {
     // Display on widget info
     pub widget_open: bool,
     pub current_point: ProcessedPosition,
-    pub current_distance: f64,
-    pub current_heading: f64,
+    pub current_geometry: RelativeGeometry,
+    /// Speed toward target in m/s, from successive captures
+    pub closing_speed: f64,
+    /// Quantum travel time to target in seconds, None if drive can not make it
+    pub qt_eta: Option<f64>,
+    /// Reference bodies to aim at target, only in space
+    pub space_references: Vec<AngularReference>,
+    /// QT markers to reach target, best first
+    pub qt_candidates: Vec<QtCandidate>,
+
+    // Prediction of target position
+    /// Seconds ahead
+    pub predict_offset: f64,
+    /// Longitude fixed in space, in degrees
+    pub predict_longitude: f64,
+    pub prediction: Option<Prediction>,
+    /// Seconds until target is under the star
+    pub subsolar_time: Option<f64>,
+    /// Seconds until target is under predict_longitude
+    pub longitude_time: Option<f64>,
+    last_observation: Option<(DateTime<Utc>, f64)>,
 }
 
 #[derive(Debug, Clone)]
@@ -58,11 +85,11 @@ pub struct Path {
 
     // Display on widget info
     pub widget_open: bool,
+    pub widget_speed_profile: bool,
 
     // Display info for current highligted point
     pub current_index: usize, // index = 0 mean no highlight !
-    pub current_distance: f64,
-    pub current_heading: f64,
+    pub current_geometry: RelativeGeometry,
 }
 
 impl Target {
@@ -70,58 +97,83 @@ impl Target {
         Self {
             widget_open: true,
             current_point: poi_to_processed_point(target, database),
-            current_distance: NAN,
-            current_heading: NAN,
+            current_geometry: RelativeGeometry::default(),
+            closing_speed: f64::NAN,
+            qt_eta: None,
+            space_references: Vec::new(),
+            qt_candidates: Vec::new(),
+            predict_offset: 0.0,
+            predict_longitude: 0.0,
+            prediction: None,
+            subsolar_time: None,
+            longitude_time: None,
+            last_observation: None,
             map_color: random_color32(),
             map_shape: MarkerShape::Diamond,
             map_radius: 4.0,
         }
     }
 
-    pub fn update(&mut self, database: &Database, current_position: Option<&ProcessedPosition>) {
+    pub fn update(
+        &mut self,
+        database: &Database,
+        current_position: Option<&ProcessedPosition>,
+        drive: &QtDrive,
+        latency: f64,
+    ) {
         if let Some(complete_position) = current_position {
-            let target_container = database.get(&self.current_point.container_name).unwrap();
-            // #Grab the rotation speed of the container in the Database and convert it in degrees/s
-            let target_rotation_speed_in_hours_per_rotation = target_container.rotation_speed;
-
-            let target_rotation_speed_in_degrees_per_second =
-                0.1 * (1.0 / target_rotation_speed_in_hours_per_rotation); //TODO handle divide by 0
-                                                                           // #Get the actual rotation state in degrees using the rotation speed of the container, the actual time and a rotational adjustment value
-            let target_rotation_state_in_degrees = (target_rotation_speed_in_degrees_per_second
-                * complete_position.time_elapsed
-                + target_container.rotation_adjust)
-                % 360.0;
-
-            // Target rotated coordinates (still relative to container center)
-            let target_rotated_coordinates = self
-                .current_point
-                .local_coordinates
-                .rotate(target_rotation_state_in_degrees.to_radians());
-
-            // #---------------------------------------------------Distance to target----------------------------------------------------------
-            let delta_distance =
-                if complete_position.container_name == self.current_point.container_name {
-                    // println!("{:?}\n{:?}\n{:?}\n\n ",self.current_point.local_coordinates - complete_position.local_coordinates, self.current_point.local_coordinates, complete_position.local_coordinates );
-                    self.current_point.local_coordinates - complete_position.local_coordinates
-                } else {
-                    // println!("not same container ! houlala");
-                    // println!("{} - {} ",complete_position.container_name, self.current_point.container_name );
-                    target_rotated_coordinates + target_container.coordinates
-                    // - complete_position.local_coordinates // why this ?
-                    // + complete_position.absolute_coordinates // and why a + ?
-                    - complete_position.space_time_position.coordinates
-                };
-            self.current_distance = delta_distance.norm();
-
-            // #----------------------------------------------------------Heading--------------------------------------------------------------
-            // If planetary !
-            self.current_heading = (complete_position
-                // .space_time_position
-                // .coordinates
-                .local_coordinates
-                .loxodromie_to(self.current_point.local_coordinates)
-                + 2.0 * PI)
-                % (2.0 * PI);
+            let destination = Destination::Position(&self.current_point);
+            self.current_geometry = relative_geometry(complete_position, destination, database)
+                .unwrap_or_default()
+                .with_uncertainty(
+                    complete_position.uncertainty(database, latency),
+                    self.current_point.uncertainty(database, latency),
+                );
+
+            // Closing speed only change on a new capture
+            let timestamp = complete_position.space_time_position.timestamp;
+            match self.last_observation {
+                Some((last_timestamp, _)) if last_timestamp == timestamp => {}
+                Some((last_timestamp, last_distance)) if last_timestamp < timestamp => {
+                    let dt = (timestamp - last_timestamp).num_milliseconds() as f64 / 1000.0;
+                    self.closing_speed =
+                        (last_distance - self.current_geometry.distance) * 1000.0 / dt;
+                    self.last_observation = Some((timestamp, self.current_geometry.distance));
+                }
+                _ => {
+                    self.closing_speed = f64::NAN;
+                    self.last_observation = Some((timestamp, self.current_geometry.distance));
+                }
+            }
+
+            self.space_references = if self.current_geometry.frame == Frame::Absolute {
+                angular_references(complete_position, destination, database, 3)
+            } else {
+                Vec::new()
+            };
+            self.qt_eta = drive.travel_time(self.current_geometry.distance);
+            self.qt_candidates =
+                rank_qt_markers(complete_position, &self.current_point, database, drive);
+
+            self.prediction = predict(
+                complete_position,
+                destination,
+                self.predict_offset,
+                database,
+            );
+            let time_elapsed = complete_position.time_elapsed;
+            let local_coordinates = self.current_point.local_coordinates;
+            if let Some(container) = database.get(&self.current_point.container_name) {
+                self.subsolar_time = subsolar_longitude(container, database).and_then(|l| {
+                    time_until_longitude(container, local_coordinates, l, time_elapsed)
+                });
+                self.longitude_time = time_until_longitude(
+                    container,
+                    local_coordinates,
+                    self.predict_longitude.to_radians(),
+                    time_elapsed,
+                );
+            }
         }
     }
 }
@@ -140,22 +192,86 @@ impl Path {
             map_displayed: true,
 
             widget_open: false,
+            widget_speed_profile: false,
             current_index: 0,
-            current_distance: 0.0,
-            current_heading: 0.0,
+            current_geometry: RelativeGeometry::default(),
         }
     }
 
-    pub fn update(&mut self, database: &Database, complete_position: Option<&ProcessedPosition>) {
-        // Update path lenght
-        self.length = 0.0;
-        if !self.history.is_empty() {
-            for i in 1..self.history.len() {
-                self.length += (self.history[i - 1].local_coordinates
-                    - self.history[i].local_coordinates)
-                    .norm();
-            }
+    /// New path made of given points, with a random color
+    pub fn derive(&self, name: String, history: Vec<ProcessedPosition>) -> Path {
+        let mut path = Path::new(name);
+        path.map_color = random_color32();
+        path.history = history;
+        path
+    }
+
+    /// Split in two at highlighted point, highlighted point end the first path
+    pub fn split(&self, paths: &Paths) -> Option<(Path, Path)> {
+        if (self.current_index == 0) | (self.current_index >= self.history.len()) {
+            return None;
         }
+        let first = self.derive(
+            unique_path_name(paths, &format!("{} (1)", self.name)),
+            self.history[..self.current_index].to_vec(),
+        );
+        let second = self.derive(
+            unique_path_name(paths, &format!("{} (2)", self.name)),
+            self.history[self.current_index..].to_vec(),
+        );
+        Some((first, second))
+    }
+
+    pub fn reversed(&self, paths: &Paths) -> Path {
+        let mut history = self.history.clone();
+        history.reverse();
+        self.derive(
+            unique_path_name(paths, &format!("{} reversed", self.name)),
+            history,
+        )
+    }
+
+    /// Keep points from start to end, 1-based and included like current_index
+    pub fn cropped(&self, paths: &Paths, start: usize, end: usize) -> Path {
+        let end = end.min(self.history.len());
+        let start = start.clamp(1, end.max(1));
+        self.derive(
+            unique_path_name(paths, &format!("{} [{start}-{end}]", self.name)),
+            self.history
+                .get(start - 1..end)
+                .unwrap_or_default()
+                .to_vec(),
+        )
+    }
+
+    pub fn duplicate(&self, paths: &Paths) -> Path {
+        self.derive(
+            unique_path_name(paths, &format!("{} copy", self.name)),
+            self.history.clone(),
+        )
+    }
+
+    /// Merge with other path, sorted by timestamp or other appended at the end
+    pub fn merged(&self, paths: &Paths, other: &Path, by_time: bool) -> Path {
+        let mut history = self.history.clone();
+        history.extend(other.history.iter().cloned());
+        if by_time {
+            history.sort_by_key(|p| p.space_time_position.timestamp);
+        }
+        self.derive(
+            unique_path_name(paths, &format!("{} + {}", self.name, other.name)),
+            history,
+        )
+    }
+
+    pub fn update(
+        &mut self,
+        database: &Database,
+        complete_position: Option<&ProcessedPosition>,
+        latency: f64,
+    ) {
+        // Update path lenght
+        self.length = path_length(&self.history);
         // Update path duration
         if let Some(point) = self.history.last() {
             self.duration =
@@ -166,45 +282,14 @@ impl Path {
         if let Some(complete_position) = complete_position {
             if !self.history.is_empty() {
                 let index = self.current_index.clamp(1, self.history.len()) - 1;
-                let target_local_coordinates = self.history[index].local_coordinates;
-
-                let target_container = database.get(&self.history[0].container_name).unwrap();
-                // #Grab the rotation speed of the container in the Database and convert it in degrees/s
-                let target_rotation_speed_in_hours_per_rotation = target_container.rotation_speed;
-
-                let target_rotation_speed_in_degrees_per_second =
-                    0.1 * (1.0 / target_rotation_speed_in_hours_per_rotation); //TODO handle divide by 0
-                                                                               // #Get the actual rotation state in degrees using the rotation speed of the container, the actual time and a rotational adjustment value
-                let target_rotation_state_in_degrees = (target_rotation_speed_in_degrees_per_second
-                    * complete_position.time_elapsed
-                    + target_container.rotation_adjust)
-                    % 360.0;
-
-                // Target rotated coordinates (still relative to container center)
-                let target_rotated_coordinates =
-                    target_local_coordinates.rotate(target_rotation_state_in_degrees.to_radians());
-
-                // #---------------------------------------------------Distance to target----------------------------------------------------------
-                let delta_distance =
-                    if complete_position.container_name == self.history[index].container_name {
-                        target_local_coordinates - complete_position.local_coordinates
-                    } else {
-                        target_rotated_coordinates + target_container.coordinates
-                    // - complete_position.local_coordinates // why this ?
-                    // + complete_position.absolute_coordinates // and why a + ?
-                    - complete_position.space_time_position.coordinates
-                    };
-                self.current_distance = delta_distance.norm();
-
-                // #----------------------------------------------------------Heading--------------------------------------------------------------
-                // If planetary !
-                self.current_heading = (complete_position
-                    // .space_time_position
-                    // .coordinates
-                    .local_coordinates
-                    .loxodromie_to(target_local_coordinates)
-                    + 2.0 * PI)
-                    % (2.0 * PI);
+                let point = &self.history[index];
+                self.current_geometry =
+                    relative_geometry(complete_position, Destination::Position(point), database)
+                        .unwrap_or_default()
+                        .with_uncertainty(
+                            complete_position.uncertainty(database, latency),
+                            point.uncertainty(database, latency),
+                        );
             }
         }
     }
@@ -15,19 +15,34 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
+mod calibration;
+mod dedupe;
+mod descent;
+mod edit_history;
 mod mainlib;
+mod path_tools;
+mod race;
+mod refine;
+mod replay;
+mod route;
+pub use calibration::*;
+pub use dedupe::*;
+pub use descent::*;
+pub use edit_history::*;
 pub use mainlib::*;
+pub use path_tools::*;
+pub use race::*;
+pub use refine::*;
+pub use replay::*;
+pub use route::*;
 
-use crate::{prelude::*, REFERENCE_TIME};
+use crate::prelude::*;
 
 use arboard::Clipboard;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use egui::{Key, Modifiers};
 use regex::Regex;
-use std::{
-    collections::HashMap,
-    f64::{consts::PI, NAN},
-    time::Duration,
-};
+use std::{collections::HashMap, f64::consts::FRAC_PI_2, time::Duration};
 use uuid::Uuid;
 
 pub struct MyEguiApp {
@@ -38,6 +53,12 @@ pub struct MyEguiApp {
 
     // Data
     pub database: Database,
+    pub reference_time: DateTime<Utc>,
+    pub custom_pois: CustomPoiStore,
+    /// Outcome of last CustomPoi.json migration
+    pub migration_report: String,
+    pub settings: Settings,
+    pub qt_drives: Vec<QtDrive>,
 
     // App State
     pub current_heading: f64,
@@ -51,11 +72,35 @@ pub struct MyEguiApp {
     pub global_paths: Paths,
     pub path_add_point: bool,
     pub path_selector: String,
+    pub path_tools: PathTools,
+    pub replay: Replay,
+    pub race: Race,
+    pub route: RoutePlanner,
+    pub descent: Descent,
+    pub refine: Refine,
+    pub dedupe: Dedupe,
 
     // Targets
     pub global_targets: Targets,
     pub target_selector_poi: String,
     pub target_selector_container: String,
+
+    // Map
+    pub map_container: String,
+    pub map_container_follow: bool,
+
+    // Calibration
+    pub calibration: Calibration,
+
+    // Reprocess
+    pub reprocess_widget: bool,
+    pub reprocess_file_name: String,
+    pub reprocess_report: Vec<ReprocessDiff>,
+    /// Reprocessed captures of file, waiting for user to accept report
+    pub reprocess_file_pending: Option<Vec<ProcessedPosition>>,
+
+    // Undo & Redo
+    pub edit_history: EditHistory,
 }
 
 impl MyEguiApp {
@@ -65,7 +110,8 @@ impl MyEguiApp {
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
         // for e.g. egui::PaintCallback.
 
-        let database = load_database();
+        let custom_pois = CustomPoiStore::load();
+        let (database, reference_time) = load_database(&custom_pois);
         // Hardcode targets example for Daymar Rally
         // let target1 = database
         //     .get("Yela")
@@ -109,6 +155,11 @@ impl MyEguiApp {
 
         MyEguiApp {
             database,
+            reference_time,
+            custom_pois,
+            migration_report: String::new(),
+            settings: load_settings(),
+            qt_drives: load_qt_drives(),
             clipboard,
             space_time_position: SpaceTimePosition::default(),
             path_name_io: String::new(),
@@ -119,9 +170,24 @@ impl MyEguiApp {
             global_targets: targets,
             path_selector: "Self".to_string(),
             path_add_point: true,
+            path_tools: PathTools::default(),
+            replay: Replay::default(),
+            race: Race::default(),
+            route: RoutePlanner::default(),
+            descent: Descent::default(),
+            refine: Refine::default(),
+            dedupe: Dedupe::default(),
             target_selector_poi: String::new(),
             target_selector_container: String::new(),
-            current_heading: NAN,
+            current_heading: f64::NAN,
+            map_container: "Daymar".to_string(),
+            map_container_follow: true,
+            calibration: Calibration::default(),
+            reprocess_widget: false,
+            reprocess_file_name: String::new(),
+            reprocess_report: Vec::new(),
+            reprocess_file_pending: None,
+            edit_history: EditHistory::default(),
         }
     }
 
@@ -130,47 +196,102 @@ impl MyEguiApp {
         self.global_history_index = self.global_history.len() - 1;
     }
 
-    pub fn new_coordinates_input(&mut self) {
-        // create ProcessedPosition from input
-        let container =
-            get_current_container(&self.space_time_position.coordinates, &self.database);
-        let time_elapsed = (self.space_time_position.timestamp - *REFERENCE_TIME)
-            .num_nanoseconds()
-            .unwrap() as f64
-            / 1e9;
-        let local_coordinates = self
-            .space_time_position
-            .coordinates
-            .transform_to_local(time_elapsed, &container);
-        let (latitude, longitude, altitude);
-
-        if container.name != "Space" {
-            latitude = local_coordinates.latitude();
-            longitude = local_coordinates.longitude();
-            altitude = local_coordinates.altitude(container.radius_body);
-        } else {
-            latitude = NAN;
-            longitude = NAN;
-            altitude = NAN;
+    /// Seconds elapsed since reference time, corrected by clock offset
+    pub fn time_elapsed(&self, timestamp: DateTime<Utc>) -> f64 {
+        time_elapsed(timestamp, self.reference_time, self.settings.clock_offset)
+    }
+
+    /// Process again all stored captures, to apply new time settings or rotation
+    pub fn reprocess_positions(&mut self) -> Vec<ReprocessDiff> {
+        // New report, pending file can not be accepted anymore
+        self.reprocess_file_pending = None;
+        let mut positions: Vec<&mut ProcessedPosition> = self.global_history.iter_mut().collect();
+        for path in self.global_paths.values_mut() {
+            positions.extend(path.history.iter_mut());
+        }
+        positions.extend(self.calibration.samples.iter_mut());
+        reprocess_positions(
+            positions,
+            &self.database,
+            self.reference_time,
+            self.settings.clock_offset,
+        )
+    }
+
+    /// Process again captures of a single path
+    pub fn reprocess_path(&mut self, name: &str) -> Vec<ReprocessDiff> {
+        // New report, pending file can not be accepted anymore
+        self.reprocess_file_pending = None;
+        match self.global_paths.get_mut(name) {
+            Some(path) => reprocess_positions(
+                path.history.iter_mut(),
+                &self.database,
+                self.reference_time,
+                self.settings.clock_offset,
+            ),
+            None => Vec::new(),
+        }
+    }
+
+    /// Process again captures of all paths
+    pub fn reprocess_paths(&mut self) -> Vec<ReprocessDiff> {
+        // New report, pending file can not be accepted anymore
+        self.reprocess_file_pending = None;
+        reprocess_positions(
+            self.global_paths
+                .values_mut()
+                .flat_map(|path| path.history.iter_mut()),
+            &self.database,
+            self.reference_time,
+            self.settings.clock_offset,
+        )
+    }
+
+    /// Process again captures of an exported path file, kept until report is accepted
+    pub fn reprocess_file(&mut self, filename: &String) -> Vec<ReprocessDiff> {
+        let mut history = import_history(filename);
+        if history.is_empty() {
+            self.reprocess_file_pending = None;
+            return Vec::new();
+        }
+        let report = reprocess_positions(
+            history.iter_mut(),
+            &self.database,
+            self.reference_time,
+            self.settings.clock_offset,
+        );
+        self.reprocess_file_pending = Some(history);
+        report
+    }
+
+    /// Write accepted reprocessed file next to the original one, as `<name>-reprocessed.json`
+    pub fn save_reprocessed_file(&mut self) {
+        if let Some(history) = self.reprocess_file_pending.take() {
+            save_history(
+                &format!("{}-reprocessed", self.reprocess_file_name),
+                &history,
+            );
         }
+    }
 
+    pub fn new_coordinates_input(&mut self) {
+        // create ProcessedPosition from input
+        let time_elapsed = self.time_elapsed(self.space_time_position.timestamp);
         let name = "# ".to_owned() + &Uuid::new_v4().to_string()[9..18].to_uppercase();
+        let new_position =
+            ProcessedPosition::new(self.space_time_position, time_elapsed, name, &self.database);
 
-        let new_position = ProcessedPosition {
-            space_time_position: self.space_time_position,
-            local_coordinates,
-            time_elapsed,
-            container_name: container.name,
-            name,
-            latitude,
-            longitude,
-            altitude,
-            color: None,
-        };
+        // Map follow current container
+        if self.map_container_follow {
+            self.map_container = new_position.container_name.clone();
+        }
 
         // Add it to history
         self.add_to_global(&new_position);
 
+        // Compare to race reference
+        self.race.on_capture(&new_position, &self.global_paths);
+
         if self.path_add_point {
             if let Some(path) = self.global_paths.get_mut(&self.path_selector) {
                 if path.history.is_empty() {
@@ -185,23 +306,29 @@ impl MyEguiApp {
     }
 
     pub fn new_coordinates_from_map(&mut self, latitude: f64, longitude: f64) {
-        let latitude = latitude.clamp(-PI, PI);
+        let latitude = latitude.clamp(-FRAC_PI_2, FRAC_PI_2);
 
-        let container_name = "Daymar".to_string(); //TODO HardCode Daymar
-        let altitude = 295.0;
+        // Point is created on displayed container, at sea level
+        let container_name = self.map_container.clone();
+        let radius = match self.database.get(&container_name) {
+            Some(container) => container.radius_body,
+            None => return,
+        };
+        let geodetic = Geodetic {
+            latitude,
+            longitude,
+            altitude: 0.0,
+        };
 
         let timestamp = Utc::now();
-        let z = altitude * latitude.sin();
-        let x = -altitude * latitude.cos() * longitude.sin();
-        let y = altitude * latitude.cos() * longitude.cos();
-
-        let local_coordinates = Vec3d { x, y, z };
+        let local_coordinates = geodetic.to_local(radius);
 
         let space_time_position = SpaceTimePosition {
             coordinates: Vec3d::default(),
             timestamp,
+            precision: 0.0,
         };
-        let time_elapsed = (timestamp - *REFERENCE_TIME).num_nanoseconds().unwrap() as f64 / 1e9;
+        let time_elapsed = self.time_elapsed(timestamp);
 
         let name = "# ".to_owned() + &Uuid::new_v4().to_string()[9..18].to_uppercase();
 
@@ -211,9 +338,9 @@ impl MyEguiApp {
             time_elapsed,
             container_name,
             name,
-            latitude,
-            longitude,
-            altitude,
+            latitude: geodetic.latitude,
+            longitude: geodetic.longitude,
+            altitude: geodetic.altitude,
             color: None,
         };
 
@@ -222,17 +349,49 @@ impl MyEguiApp {
 
         if self.path_add_point {
             if let Some(path) = self.global_paths.get_mut(&self.path_selector) {
-                if path.history.is_empty() {
-                    path.history.push(new_position.clone());
-                } else {
-                    path.history
-                        .insert(path.current_index, new_position.clone());
-                }
+                let index = path.current_index.min(path.history.len());
+                path.history.insert(index, new_position.clone());
                 path.current_index += 1;
+                self.edit_history.push(Edit::PathPointAdded {
+                    name: path.name.clone(),
+                    index,
+                    point: new_position,
+                });
             }
         }
     }
 
+    /// Save position as custom POI, add it to database
+    pub fn save_position_to_poi(&mut self, position: &ProcessedPosition) {
+        self.save_poi(processed_point_to_poi(position));
+    }
+
+    /// Save a custom POI, in database and custom POI store
+    pub fn save_poi(&mut self, new_poi: Poi) {
+        let before = self
+            .database
+            .get(&new_poi.container)
+            .and_then(|c| c.poi.get(&new_poi.name))
+            .cloned()
+            .map(Box::new);
+        let before_custom = self
+            .custom_pois
+            .get(&new_poi.container, &new_poi.name)
+            .cloned()
+            .map(Box::new);
+
+        self.custom_pois.save(&new_poi);
+        // Add to database
+        if let Some(container) = self.database.get_mut(&new_poi.container) {
+            container.poi.insert(new_poi.name.clone(), new_poi.clone());
+        }
+        self.edit_history.push(Edit::PoiSaved {
+            before,
+            before_custom,
+            after: Box::new(new_poi),
+        });
+    }
+
     fn get_space_time_position(&mut self) -> Option<SpaceTimePosition> {
         let content = match self.clipboard.get_text() {
             Ok(content) => content,
@@ -253,9 +412,17 @@ impl MyEguiApp {
             caps["y"].parse::<f64>().unwrap() / 1000.0,
             caps["z"].parse::<f64>().unwrap() / 1000.0,
         );
+        // Least precise axis, in km
+        let decimals = ["x", "y", "z"]
+            .iter()
+            .map(|axis| caps[*axis].split('.').nth(1).map_or(0, str::len))
+            .min()
+            .unwrap_or(0);
+        let precision = 10f64.powi(-(decimals as i32)) / 1000.0;
         Some(SpaceTimePosition {
             coordinates,
             timestamp,
+            precision,
         })
     }
 }
@@ -287,12 +454,33 @@ impl eframe::App for MyEguiApp {
 
         // Update all NEW path
         for (_, path) in self.global_paths.iter_mut() {
-            path.update(&self.database, live_point);
+            path.update(&self.database, live_point, self.settings.capture_latency);
         }
 
+        self.path_tools.update(&self.global_paths, &self.database);
+        self.replay
+            .update(&self.global_paths, &self.database, live_point);
+
         // Update all NEW target
+        let drive = find_qt_drive(&self.qt_drives, &self.settings.qt_drive).unwrap();
         for target in self.global_targets.iter_mut() {
-            target.update(&self.database, live_point);
+            target.update(
+                &self.database,
+                live_point,
+                drive,
+                self.settings.capture_latency,
+            );
+        }
+        self.descent
+            .update(&self.global_targets, &self.database, live_point);
+
+        // Undo & Redo, unless a text field use keyboard
+        if !ctx.wants_keyboard_input() {
+            if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z)) {
+                self.redo();
+            } else if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::Z)) {
+                self.undo();
+            }
         }
 
         // Display NEW everything
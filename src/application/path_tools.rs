@@ -0,0 +1,149 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PathOperation {
+    #[default]
+    Simplify,
+    ResampleDistance,
+    ResampleTime,
+    RemoveStationary,
+}
+
+/// Simplification & resampling of a path, previewed on map before apply, and operations
+/// creating new paths from it
+#[derive(Debug)]
+pub struct PathTools {
+    pub widget_open: bool,
+    pub path: String,
+    pub operation: PathOperation,
+    /// Douglas–Peucker tolerance in km
+    pub tolerance: f64,
+    /// Resampling step in km
+    pub distance_step: f64,
+    /// Resampling step in seconds
+    pub time_step: f64,
+    /// Stationary threshold in km
+    pub stationary_threshold: f64,
+    pub preview: Vec<ProcessedPosition>,
+    /// Inputs of preview, to compute it again only when they change
+    preview_source: Option<PreviewSource>,
+
+    // Operations creating new paths
+    pub crop_start: usize,
+    pub crop_end: usize,
+    pub merge_with: String,
+    pub merge_by_time: bool,
+}
+
+impl Default for PathTools {
+    fn default() -> Self {
+        Self {
+            widget_open: false,
+            path: String::new(),
+            operation: PathOperation::default(),
+            tolerance: 0.01,
+            distance_step: 0.1,
+            time_step: 10.0,
+            stationary_threshold: 0.001,
+            preview: Vec::new(),
+            preview_source: None,
+            crop_start: 1,
+            crop_end: 1,
+            merge_with: String::new(),
+            merge_by_time: true,
+        }
+    }
+}
+
+/// Path points (name, local coordinates, time) and operation a preview was computed from
+#[derive(Debug, PartialEq)]
+struct PreviewSource {
+    path: String,
+    operation: PathOperation,
+    parameter: f64,
+    points: Vec<(String, Vec3d, f64)>,
+}
+
+impl PreviewSource {
+    fn matches(&self, path: &Path, operation: PathOperation, parameter: f64) -> bool {
+        (self.path == path.name)
+            & (self.operation == operation)
+            & (self.parameter == parameter)
+            & (self.points.len() == path.history.len())
+            && self.points.iter().zip(&path.history).all(|(a, b)| {
+                (a.0 == b.name) & (a.1 == b.local_coordinates) & (a.2 == b.time_elapsed)
+            })
+    }
+}
+
+impl PathTools {
+    /// Parameter of current operation
+    fn parameter(&self) -> f64 {
+        match self.operation {
+            PathOperation::Simplify => self.tolerance,
+            PathOperation::ResampleDistance => self.distance_step,
+            PathOperation::ResampleTime => self.time_step,
+            PathOperation::RemoveStationary => self.stationary_threshold,
+        }
+    }
+
+    /// Compute preview of current operation on path history, when path or operation changed
+    pub fn update(&mut self, paths: &Paths, database: &Database) {
+        let path = match paths.get(&self.path) {
+            Some(path) if self.widget_open => path,
+            _ => {
+                self.preview = Vec::new();
+                self.preview_source = None;
+                return;
+            }
+        };
+        let parameter = self.parameter();
+        if self
+            .preview_source
+            .as_ref()
+            .is_some_and(|source| source.matches(path, self.operation, parameter))
+        {
+            return;
+        }
+
+        self.preview = match self.operation {
+            PathOperation::Simplify => simplify_path(&path.history, self.tolerance),
+            PathOperation::ResampleDistance => {
+                resample_path_distance(&path.history, self.distance_step, database)
+            }
+            PathOperation::ResampleTime => {
+                resample_path_time(&path.history, self.time_step, database)
+            }
+            PathOperation::RemoveStationary => {
+                remove_stationary(&path.history, self.stationary_threshold)
+            }
+        };
+        self.preview_source = Some(PreviewSource {
+            path: path.name.clone(),
+            operation: self.operation,
+            parameter,
+            points: path
+                .history
+                .iter()
+                .map(|p| (p.name.clone(), p.local_coordinates, p.time_elapsed))
+                .collect(),
+        });
+    }
+}
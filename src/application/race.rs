@@ -0,0 +1,122 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Comparison of a live capture against reference path
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RaceDelta {
+    pub name: String,
+    pub timestamp: DateTime<Utc>,
+    /// Seconds since race start
+    pub time: f64,
+    /// Progress along reference, in km
+    pub distance: f64,
+    /// Seconds behind reference, negative mean ahead
+    pub delta: f64,
+    /// Distance to reference, in km
+    pub cross_track: f64,
+}
+
+/// Exported result of a race
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RaceReport {
+    pub reference: String,
+    pub start: Option<DateTime<Utc>>,
+    pub deltas: Vec<RaceDelta>,
+    /// Seconds lost (or gained if negative) on each reference segment
+    pub segment_deltas: Vec<f64>,
+}
+
+/// Live captures raced against a recorded reference path
+#[derive(Debug, Default)]
+pub struct Race {
+    pub widget_open: bool,
+    pub reference: String,
+    pub active: bool,
+    pub start: Option<DateTime<Utc>>,
+    pub deltas: Vec<RaceDelta>,
+    /// Delta when first reaching each point of reference
+    pub checkpoints: Vec<f64>,
+    pub export_name: String,
+}
+
+impl Race {
+    /// Reset race, it start on next capture
+    pub fn arm(&mut self) {
+        self.active = true;
+        self.start = None;
+        self.deltas = Vec::new();
+        self.checkpoints = Vec::new();
+    }
+
+    pub fn on_capture(&mut self, position: &ProcessedPosition, paths: &Paths) {
+        let Some(reference) = paths.get(&self.reference).filter(|_| self.active) else {
+            return;
+        };
+        let start = *self
+            .start
+            .get_or_insert(position.space_time_position.timestamp);
+        let time =
+            (position.space_time_position.timestamp - start).num_milliseconds() as f64 / 1000.0;
+
+        // Progress only forward on reference
+        let from = self
+            .checkpoints
+            .len()
+            .saturating_sub(1)
+            .min(reference.history.len().saturating_sub(2));
+        let Some(progress) = project_on_path(&reference.history, position, from) else {
+            return;
+        };
+        let delta = time - progress.time;
+
+        while self.checkpoints.len() <= progress.segment {
+            self.checkpoints.push(delta);
+        }
+        // Reaching last point of reference end the race
+        if progress.distance >= path_length(&reference.history) {
+            self.checkpoints.push(delta);
+            self.active = false;
+        }
+
+        self.deltas.push(RaceDelta {
+            name: position.name.clone(),
+            timestamp: position.space_time_position.timestamp,
+            time,
+            distance: progress.distance,
+            delta,
+            cross_track: progress.cross_track,
+        });
+    }
+
+    pub fn segment_deltas(&self) -> Vec<f64> {
+        self.checkpoints.windows(2).map(|w| w[1] - w[0]).collect()
+    }
+
+    pub fn report(&self) -> RaceReport {
+        RaceReport {
+            reference: self.reference.clone(),
+            start: self.start,
+            deltas: self.deltas.clone(),
+            segment_deltas: self.segment_deltas(),
+        }
+    }
+}
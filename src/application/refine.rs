@@ -0,0 +1,106 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use chrono::{DateTime, Utc};
+use std::collections::BTreeSet;
+
+/// Identify a capture whatever its index, points may be inserted before it
+pub type CaptureKey = (String, DateTime<Utc>);
+
+pub fn capture_key(capture: &ProcessedPosition) -> CaptureKey {
+    (capture.name.clone(), capture.space_time_position.timestamp)
+}
+
+/// Captures selected to refine a POI location
+#[derive(Debug, Default)]
+pub struct Refine {
+    pub widget_open: bool,
+    /// Path name to pick captures from, empty for global history
+    pub source: String,
+    /// Selected captures of source
+    pub selected: BTreeSet<CaptureKey>,
+    /// Name of POI to save
+    pub name: String,
+    pub result: Option<RefinedLocation>,
+}
+
+impl Refine {
+    /// Captures of source, global history or a path
+    pub fn captures<'a>(
+        &self,
+        global_history: &'a [ProcessedPosition],
+        paths: &'a Paths,
+    ) -> &'a [ProcessedPosition] {
+        if self.source.is_empty() {
+            global_history
+        } else {
+            paths
+                .get(&self.source)
+                .map(|path| path.history.as_slice())
+                .unwrap_or_default()
+        }
+    }
+
+    /// Pick captures from another source, clearing selection
+    pub fn set_source(&mut self, source: String) {
+        if source != self.source {
+            self.source = source;
+            self.selected.clear();
+            self.result = None;
+        }
+    }
+
+    pub fn update(&mut self, global_history: &[ProcessedPosition], paths: &Paths) {
+        let captures = self.captures(global_history, paths);
+        let positions: Vec<&ProcessedPosition> = captures
+            .iter()
+            .filter(|capture| self.selected.contains(&capture_key(capture)))
+            .collect();
+        self.result = refine_location(&positions);
+    }
+}
+
+impl MyEguiApp {
+    /// Save refined location as a custom POI
+    pub fn save_refined_poi(&mut self) {
+        let Some(result) = self.refine.result.clone() else {
+            return;
+        };
+        if self.refine.name.is_empty() {
+            return;
+        }
+        let geodetic = self
+            .database
+            .get(&result.container_name)
+            .map(|container| container.local_to_geodetic(result.coordinates))
+            .unwrap_or(Geodetic::UNDEFINED);
+        self.save_poi(Poi {
+            name: self.refine.name.clone(),
+            container: result.container_name,
+            coordinates: result.coordinates,
+            quaternions: None,
+            marker: None,
+            latitude: Some(geodetic.latitude),
+            longitude: Some(geodetic.longitude),
+            altitude: Some(geodetic.altitude),
+            samples: Some(result.samples),
+            spread: Some(result.spread),
+        });
+    }
+}
@@ -0,0 +1,134 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use std::{f64::consts::PI, time::Instant};
+
+/// Replay of a recorded path following its timestamps
+#[derive(Debug)]
+pub struct Replay {
+    pub widget_open: bool,
+    pub path: String,
+    pub playing: bool,
+    /// Replay speed multiplier
+    pub speed_multiplier: f64,
+    /// Seconds since first point of path
+    pub time: f64,
+    pub duration: f64,
+    last_frame: Option<Instant>,
+
+    // Replayed state at current time
+    pub position: Option<ProcessedPosition>,
+    pub heading: f64,
+    /// Speed in m/s
+    pub speed: f64,
+    /// Distance in km from live position to replayed one
+    pub ghost_distance: f64,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self {
+            widget_open: false,
+            path: String::new(),
+            playing: false,
+            speed_multiplier: 1.0,
+            time: 0.0,
+            duration: 0.0,
+            last_frame: None,
+            position: None,
+            heading: f64::NAN,
+            speed: f64::NAN,
+            ghost_distance: f64::NAN,
+        }
+    }
+}
+
+impl Replay {
+    pub fn start(&mut self, path: &str) {
+        *self = Replay {
+            widget_open: true,
+            path: path.to_string(),
+            speed_multiplier: self.speed_multiplier,
+            ..Default::default()
+        };
+    }
+
+    pub fn update(
+        &mut self,
+        paths: &Paths,
+        database: &Database,
+        live_point: Option<&ProcessedPosition>,
+    ) {
+        let history = match paths.get(&self.path) {
+            Some(path) if self.widget_open & !path.history.is_empty() => &path.history,
+            _ => {
+                self.position = None;
+                return;
+            }
+        };
+        // Monotonic, so reversed paths replay too
+        let times = monotonic_time(history);
+        self.duration = times.last().unwrap().max(0.0);
+
+        // Advance with real time
+        let now = Instant::now();
+        if self.playing {
+            if let Some(last_frame) = self.last_frame {
+                self.time += (now - last_frame).as_secs_f64() * self.speed_multiplier;
+            }
+            if self.time >= self.duration {
+                self.playing = false;
+            }
+        }
+        self.last_frame = Some(now);
+        self.time = self.time.clamp(0.0, self.duration);
+
+        // Segment containing current time
+        let index = times
+            .partition_point(|t| *t <= self.time)
+            .clamp(1, history.len())
+            - 1;
+        let next = (index + 1).min(history.len() - 1);
+        let (a, b) = (&history[index], &history[next]);
+        let dt = times[next] - times[index];
+        let same_container = a.container_name == b.container_name;
+
+        let mut position = if (dt > 0.0) & same_container {
+            interpolate(a, b, (self.time - times[index]) / dt, database)
+        } else {
+            a.clone()
+        };
+        position.name = "Replay".to_string();
+
+        if (dt > 0.0) & same_container {
+            self.heading =
+                (a.local_coordinates.loxodromie_to(b.local_coordinates) + 2.0 * PI) % (2.0 * PI);
+            self.speed = (b.local_coordinates - a.local_coordinates).norm() * 1000.0 / dt;
+        } else {
+            self.heading = f64::NAN;
+            self.speed = f64::NAN;
+        }
+
+        self.ghost_distance = live_point
+            .and_then(|live| relative_geometry(live, Destination::Position(&position), database))
+            .map(|geometry| geometry.distance)
+            .unwrap_or(f64::NAN);
+        self.position = Some(position);
+    }
+}
@@ -0,0 +1,150 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+/// Ordered stops and the itinerary to reach them
+#[derive(Debug)]
+pub struct RoutePlanner {
+    pub widget_open: bool,
+    pub stops: Vec<RouteStop>,
+    pub selector_container: String,
+    pub selector_poi: String,
+    /// Flight speed on surface legs, in m/s
+    pub cruise_speed: f64,
+    pub legs: Vec<Leg>,
+    pub file_name: String,
+
+    // Visit order of targets in one container
+    pub visit_return: bool,
+    pub visit_container: String,
+    /// Local coordinates from start through ordered targets
+    pub visit_route: Vec<Vec3d>,
+    /// Surface distance of visit route, in km
+    pub visit_distance: f64,
+    /// Why last optimization did nothing
+    pub visit_message: String,
+}
+
+impl Default for RoutePlanner {
+    fn default() -> Self {
+        Self {
+            widget_open: false,
+            stops: Vec::new(),
+            selector_container: String::new(),
+            selector_poi: String::new(),
+            cruise_speed: 150.0,
+            legs: Vec::new(),
+            file_name: String::new(),
+            visit_return: false,
+            visit_container: String::new(),
+            visit_route: Vec::new(),
+            visit_distance: f64::NAN,
+            visit_message: String::new(),
+        }
+    }
+}
+
+impl RoutePlanner {
+    /// POI of each stop still in database
+    pub fn stop_pois(&self, database: &Database) -> Vec<Poi> {
+        self.stops
+            .iter()
+            .filter_map(|stop| database.get(&stop.container)?.poi.get(&stop.name))
+            .cloned()
+            .collect()
+    }
+
+    pub fn plan(&mut self, start: &ProcessedPosition, database: &Database, drive: &QtDrive) {
+        self.legs = plan_route(
+            start,
+            &self.stop_pois(database),
+            database,
+            drive,
+            self.cruise_speed,
+        );
+    }
+
+    pub fn total_distance(&self) -> f64 {
+        self.legs.iter().map(|leg| leg.distance).sum()
+    }
+
+    pub fn total_duration(&self) -> f64 {
+        self.legs.iter().map(|leg| leg.duration).sum()
+    }
+}
+
+impl MyEguiApp {
+    /// Reorder targets of current container in shortest visit order from current position
+    pub fn optimize_targets(&mut self) {
+        let Some(start) = self
+            .global_history_index
+            .checked_sub(1)
+            .and_then(|i| self.global_history.get(i))
+        else {
+            return;
+        };
+        let Some(container) = self.database.get(&start.container_name) else {
+            return;
+        };
+        // Every surface distance would be 0, any order would do
+        if container.radius_body == 0.0 {
+            self.route.visit_message = format!("No surface to optimize on in {}", container.name);
+            return;
+        }
+        self.route.visit_message = String::new();
+        let (inside, outside): (Vec<Target>, Vec<Target>) = self
+            .global_targets
+            .iter()
+            .cloned()
+            .partition(|t| t.current_point.container_name == container.name);
+        let points: Vec<Vec3d> = inside
+            .iter()
+            .map(|t| t.current_point.local_coordinates)
+            .collect();
+        let order = optimize_visit_order(
+            start.local_coordinates,
+            &points,
+            container.radius_body,
+            self.route.visit_return,
+        );
+
+        // Route drawn on map
+        let mut visit_route = vec![start.local_coordinates];
+        visit_route.extend(order.iter().map(|i| points[*i]));
+        if self.route.visit_return {
+            visit_route.push(start.local_coordinates);
+        }
+        self.route.visit_distance = visit_route
+            .windows(2)
+            .map(|w| great_circle_distance(w[0], w[1], container.radius_body))
+            .sum();
+        self.route.visit_route = visit_route;
+        self.route.visit_container = container.name.clone();
+
+        let before = self.global_targets.clone();
+        self.global_targets = order
+            .iter()
+            .map(|i| inside[*i].clone())
+            .chain(outside)
+            .collect();
+        self.edit_history.push(Edit::TargetsReordered {
+            before,
+            after: self.global_targets.clone(),
+        });
+    }
+}
@@ -0,0 +1,267 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+/// Outcome of a rotation calibration on a container
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationResult {
+    pub rotation_adjust: f64,
+    pub rotation_speed: f64,
+    /// RMS distance (km) between captures and POI with database values
+    pub residual_before: f64,
+    /// RMS distance (km) between captures and POI with solved values
+    pub residual_after: f64,
+}
+
+/// Wrap an angle in degrees into ]-180, 180]
+fn wrap_180(a: f64) -> f64 {
+    let a = a.rem_euclid(360.0);
+    if a > 180.0 {
+        a - 360.0
+    } else {
+        a
+    }
+}
+
+/// RMS distance between captures transformed with given rotation and the POI local coordinates
+fn residual(
+    container: &Container,
+    poi: &Poi,
+    samples: &[ProcessedPosition],
+    rotation_adjust: f64,
+    rotation_speed: f64,
+) -> f64 {
+    let container = Container {
+        rotation_adjust,
+        rotation_speed,
+        ..container.clone()
+    };
+    let sum: f64 = samples
+        .iter()
+        .map(|s| {
+            let local = s
+                .space_time_position
+                .coordinates
+                .transform_to_local(s.time_elapsed, &container);
+            (local - poi.coordinates).norm().powi(2)
+        })
+        .sum();
+    (sum / samples.len() as f64).sqrt()
+}
+
+/// Rotation state in degrees of the container measured by a capture at a known POI
+fn measured_rotation_state(container: &Container, poi: &Poi, sample: &ProcessedPosition) -> f64 {
+    let poi_angle = poi.coordinates.y.atan2(poi.coordinates.x).to_degrees();
+    let relative = sample.space_time_position.coordinates - container.coordinates;
+    relative.y.atan2(relative.x).to_degrees() - poi_angle
+}
+
+/// Estimate correction (seconds) to add to clock offset from a capture at a known POI
+///
+/// The gap between measured and predicted rotation state is converted into time, so the result
+/// is only known modulo one rotation of the container. Not available on non rotating container.
+pub fn estimate_clock_offset(
+    container: &Container,
+    poi: &Poi,
+    sample: &ProcessedPosition,
+) -> Option<f64> {
+    let speed = container.rotation_speed_in_degrees_per_second();
+    if speed == 0.0 {
+        return None;
+    }
+    let predicted = container.rotation_state(sample.time_elapsed);
+    let measured = measured_rotation_state(container, poi, sample);
+    Some(wrap_180(measured - predicted) / speed)
+}
+
+/// Solve container rotation from captures taken standing at a known POI
+///
+/// Each capture gives the rotation state of the container at its time, `rotation_adjust` is the
+/// circular mean of those states brought back to time 0. With `fit_speed` and captures spread in
+/// time, a linear regression on the remaining drift also corrects `rotation_speed`.
+pub fn calibrate_rotation(
+    container: &Container,
+    poi: &Poi,
+    samples: &[ProcessedPosition],
+    fit_speed: bool,
+) -> Option<CalibrationResult> {
+    if samples.is_empty() {
+        return None;
+    }
+    let speed = container.rotation_speed_in_degrees_per_second();
+
+    // (time, rotation state in degrees) for each capture
+    let states: Vec<(f64, f64)> = samples
+        .iter()
+        .map(|s| (s.time_elapsed, measured_rotation_state(container, poi, s)))
+        .collect();
+
+    // Circular mean of rotation adjust with current speed
+    let (sin, cos) = states.iter().fold((0.0, 0.0), |(sin, cos), (t, state)| {
+        let adjust = (state - speed * t).to_radians();
+        (sin + adjust.sin(), cos + adjust.cos())
+    });
+    let mut rotation_adjust = sin.atan2(cos).to_degrees();
+    let mut rotation_speed = container.rotation_speed;
+
+    // Linear regression on drift: residual = delta_speed * (t - t_mean) + offset
+    if fit_speed & (states.len() > 1) {
+        let n = states.len() as f64;
+        let t_mean = states.iter().map(|(t, _)| t).sum::<f64>() / n;
+        let drift: Vec<(f64, f64)> = states
+            .iter()
+            .map(|(t, state)| (t - t_mean, wrap_180(state - speed * t - rotation_adjust)))
+            .collect();
+        let r_mean = drift.iter().map(|(_, r)| r).sum::<f64>() / n;
+        let var = drift.iter().map(|(dt, _)| dt * dt).sum::<f64>();
+        if var > 0.0 {
+            let delta_speed = drift.iter().map(|(dt, r)| dt * (r - r_mean)).sum::<f64>() / var;
+            let new_speed = speed + delta_speed;
+            if new_speed != 0.0 {
+                rotation_speed = 0.1 / new_speed;
+                rotation_adjust += r_mean - delta_speed * t_mean;
+            }
+        }
+    }
+    let rotation_adjust = rotation_adjust.rem_euclid(360.0);
+
+    Some(CalibrationResult {
+        rotation_adjust,
+        rotation_speed,
+        residual_before: residual(
+            container,
+            poi,
+            samples,
+            container.rotation_adjust,
+            container.rotation_speed,
+        ),
+        residual_after: residual(container, poi, samples, rotation_adjust, rotation_speed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Container as in database, rotation to calibrate
+    fn container(rotation_speed: f64, rotation_adjust: f64) -> Container {
+        Container {
+            name: "Test".to_string(),
+            coordinates: Vec3d::new(1000.0, -2000.0, 300.0),
+            radius_body: 100.0,
+            rotation_speed,
+            rotation_adjust,
+            ..Default::default()
+        }
+    }
+
+    fn poi() -> Poi {
+        Poi {
+            name: "Outpost".to_string(),
+            container: "Test".to_string(),
+            coordinates: Vec3d::new(60.0, 70.0, 20.0),
+            ..Default::default()
+        }
+    }
+
+    /// Capture standing at POI at time, on a container with true rotation
+    fn capture(truth: &Container, time_elapsed: f64) -> ProcessedPosition {
+        ProcessedPosition {
+            space_time_position: SpaceTimePosition {
+                coordinates: poi().coordinates.transform_to_absolute(time_elapsed, truth),
+                ..Default::default()
+            },
+            time_elapsed,
+            ..Default::default()
+        }
+    }
+
+    fn assert_angle_close(a: f64, b: f64, tolerance: f64) {
+        assert!(wrap_180(a - b).abs() < tolerance, "{a} != {b}");
+    }
+
+    #[test]
+    fn no_sample() {
+        assert_eq!(
+            calibrate_rotation(&container(3.0, 0.0), &poi(), &[], true),
+            None
+        );
+    }
+
+    #[test]
+    fn recover_adjust_across_wrap() {
+        let truth = container(3.0, 359.5);
+        // Rotation states from 359.5° to 361.5°, across 0°
+        let samples: Vec<ProcessedPosition> =
+            (0..7).map(|i| capture(&truth, i as f64 * 10.0)).collect();
+
+        let result = calibrate_rotation(&container(3.0, 100.0), &poi(), &samples, false).unwrap();
+        assert_angle_close(result.rotation_adjust, 359.5, 1e-9);
+        assert!((0.0..360.0).contains(&result.rotation_adjust));
+        assert_eq!(result.rotation_speed, 3.0);
+        assert!(result.residual_before > 10.0);
+        assert!(result.residual_after < 1e-9);
+    }
+
+    #[test]
+    fn recover_speed_and_adjust() {
+        for adjust in [0.5, 180.0, 359.5] {
+            let truth = container(3.1, adjust);
+            let samples: Vec<ProcessedPosition> = (0..10)
+                .map(|i| capture(&truth, 5000.0 + i as f64 * 100.0))
+                .collect();
+
+            let result =
+                calibrate_rotation(&container(3.0, adjust + 20.0), &poi(), &samples, true).unwrap();
+            assert!((result.rotation_speed - 3.1).abs() < 1e-9);
+            assert_angle_close(result.rotation_adjust, adjust, 1e-6);
+            assert!(result.residual_after < 1e-6);
+            assert!(result.residual_after < result.residual_before);
+
+            // Without speed fit, drift of samples remains
+            let result =
+                calibrate_rotation(&container(3.0, adjust + 20.0), &poi(), &samples, false)
+                    .unwrap();
+            assert_eq!(result.rotation_speed, 3.0);
+            assert!(result.residual_after > 0.1);
+            assert!(result.residual_after < result.residual_before);
+        }
+    }
+
+    #[test]
+    fn estimate_known_clock_skew() {
+        let database_container = container(3.0, 42.0);
+        for skew in [-120.0, 0.0, 35.5, 900.0] {
+            // Capture happened at 10000s while the clock reports 10000s - skew
+            let mut sample = capture(&database_container, 10000.0);
+            sample.time_elapsed -= skew;
+            let estimate = estimate_clock_offset(&database_container, &poi(), &sample).unwrap();
+            assert!((estimate - skew).abs() < 1e-6, "{estimate} != {skew}");
+        }
+    }
+
+    #[test]
+    fn no_clock_offset_without_rotation() {
+        let database_container = container(0.0, 42.0);
+        let sample = capture(&database_container, 10000.0);
+        assert_eq!(
+            estimate_clock_offset(&database_container, &poi(), &sample),
+            None
+        );
+    }
+}
@@ -0,0 +1,297 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use std::collections::HashSet;
+
+/// POIs of one container close enough to be the same place
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeProposal {
+    pub container: String,
+    /// POIs to merge, imported ones first
+    pub members: Vec<Poi>,
+    /// Members coming from custom POIs
+    pub custom: HashSet<PoiKey>,
+    /// Mean of members, weighted by their sample count
+    pub coordinates: Vec3d,
+    /// RMS distance of members to mean, in km
+    pub spread: f64,
+    /// Name kept after merge, one of members
+    pub canonical: String,
+}
+
+impl MergeProposal {
+    fn is_custom(&self, poi: &Poi) -> bool {
+        self.custom.contains(&poi_key(poi))
+    }
+
+    /// Canonical member, first one by default
+    fn canonical_member(&self) -> &Poi {
+        self.members
+            .iter()
+            .find(|poi| poi.name == self.canonical)
+            .unwrap_or(&self.members[0])
+    }
+
+    /// Merged custom POI, keeping canonical member marker and orientation
+    ///
+    /// None when canonical is imported, it is kept as is in Database.json.
+    pub fn merged(&self, database: &Database) -> Option<Poi> {
+        let canonical = self.canonical_member();
+        if !self.is_custom(canonical) {
+            return None;
+        }
+        let geodetic = database
+            .get(&self.container)
+            .map(|container| container.local_to_geodetic(self.coordinates))
+            .unwrap_or(Geodetic::UNDEFINED);
+        Some(Poi {
+            name: canonical.name.clone(),
+            container: self.container.clone(),
+            coordinates: self.coordinates,
+            quaternions: canonical.quaternions,
+            marker: canonical.marker,
+            latitude: Some(geodetic.latitude),
+            longitude: Some(geodetic.longitude),
+            altitude: Some(geodetic.altitude),
+            samples: Some(
+                self.members
+                    .iter()
+                    .map(|poi| poi.samples.unwrap_or(1))
+                    .sum(),
+            ),
+            spread: Some(self.spread),
+        })
+    }
+
+    /// Custom members replaced by merge, canonical included when custom
+    pub fn replaced(&self) -> impl Iterator<Item = &Poi> {
+        self.members.iter().filter(|poi| self.is_custom(poi))
+    }
+}
+
+/// Group POIs chained closer than threshold (km) in each container
+///
+/// Only groups with at least one custom POI are proposed, imported POIs can not be removed from
+/// Database.json. Canonical name default to an imported member, then to the most sampled one.
+pub fn propose_merges(
+    database: &Database,
+    custom: &HashSet<PoiKey>,
+    threshold: f64,
+) -> Vec<MergeProposal> {
+    let mut proposals = Vec::new();
+    for container in database.values() {
+        let pois: Vec<&Poi> = container.poi.values().collect();
+        let is_custom = |poi: &Poi| custom.contains(&poi_key(poi));
+
+        // Single linkage clusters, with union find
+        let mut parent: Vec<usize> = (0..pois.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for i in 0..pois.len() {
+            for j in i + 1..pois.len() {
+                if (pois[i].coordinates - pois[j].coordinates).norm() <= threshold {
+                    let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                    parent[a] = b;
+                }
+            }
+        }
+
+        let mut clusters: Vec<Vec<&Poi>> = vec![Vec::new(); pois.len()];
+        for (i, poi) in pois.iter().enumerate() {
+            clusters[root(&mut parent, i)].push(poi);
+        }
+
+        for mut members in clusters {
+            if (members.len() < 2) | !members.iter().any(|poi| is_custom(poi)) {
+                continue;
+            }
+            members
+                .sort_by_key(|poi| (is_custom(poi), std::cmp::Reverse(poi.samples.unwrap_or(1))));
+
+            let weights: Vec<f64> = members
+                .iter()
+                .map(|poi| poi.samples.unwrap_or(1) as f64)
+                .collect();
+            let total: f64 = weights.iter().sum();
+            let coordinates = members
+                .iter()
+                .zip(&weights)
+                .fold(Vec3d::default(), |sum, (poi, w)| sum + poi.coordinates * *w)
+                * (1.0 / total);
+            let spread = (members
+                .iter()
+                .map(|poi| {
+                    let d = (poi.coordinates - coordinates).norm();
+                    d * d
+                })
+                .sum::<f64>()
+                / members.len() as f64)
+                .sqrt();
+
+            proposals.push(MergeProposal {
+                container: container.name.clone(),
+                custom: members
+                    .iter()
+                    .filter(|poi| is_custom(poi))
+                    .map(|poi| poi_key(poi))
+                    .collect(),
+                coordinates,
+                spread,
+                canonical: members[0].name.clone(),
+                members: members.into_iter().cloned().collect(),
+            });
+        }
+    }
+    proposals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poi(container: &str, name: &str, x: f64, samples: Option<usize>) -> Poi {
+        Poi {
+            name: name.to_string(),
+            container: container.to_string(),
+            coordinates: Vec3d { x, y: 0.0, z: 0.0 },
+            samples,
+            ..Default::default()
+        }
+    }
+
+    fn database(pois: &[Poi]) -> Database {
+        let mut database = Database::new();
+        for poi in pois {
+            database
+                .entry(poi.container.clone())
+                .or_insert_with(|| Container {
+                    name: poi.container.clone(),
+                    ..Default::default()
+                })
+                .poi
+                .insert(poi.name.clone(), poi.clone());
+        }
+        database
+    }
+
+    fn all_custom(pois: &[Poi]) -> HashSet<PoiKey> {
+        pois.iter().map(poi_key).collect()
+    }
+
+    #[test]
+    fn nothing_to_merge() {
+        assert!(propose_merges(&Database::new(), &HashSet::new(), 0.5).is_empty());
+
+        let single = [poi("Daymar", "A", 0.0, None)];
+        assert!(propose_merges(&database(&single), &all_custom(&single), 0.5).is_empty());
+
+        let apart = [poi("Daymar", "A", 0.0, None), poi("Daymar", "B", 1.0, None)];
+        assert!(propose_merges(&database(&apart), &all_custom(&apart), 0.5).is_empty());
+    }
+
+    #[test]
+    fn weighted_merge_keeps_most_sampled() {
+        let pois = [
+            poi("Daymar", "A", 0.0, None),
+            poi("Daymar", "B", 0.4, Some(3)),
+        ];
+        let proposals = propose_merges(&database(&pois), &all_custom(&pois), 0.5);
+        assert_eq!(proposals.len(), 1);
+        let proposal = &proposals[0];
+        assert_eq!(proposal.container, "Daymar");
+        assert_eq!(proposal.canonical, "B");
+        assert!((proposal.coordinates.x - 0.3).abs() < 1e-12);
+        // RMS of 0.3 and 0.1
+        assert!((proposal.spread - 0.05_f64.sqrt()).abs() < 1e-12);
+
+        let merged = proposal.merged(&database(&pois)).unwrap();
+        assert_eq!(merged.name, "B");
+        assert_eq!(merged.samples, Some(4));
+    }
+
+    #[test]
+    fn chained_pois_form_one_group() {
+        // A and C are too far apart, but both close to B
+        let pois = [
+            poi("Daymar", "A", 0.0, None),
+            poi("Daymar", "B", 0.4, None),
+            poi("Daymar", "C", 0.8, None),
+        ];
+        let proposals = propose_merges(&database(&pois), &all_custom(&pois), 0.5);
+        assert_eq!(proposals.len(), 1);
+        assert_eq!(proposals[0].members.len(), 3);
+    }
+
+    #[test]
+    fn containers_are_not_mixed() {
+        let pois = [poi("Daymar", "A", 0.0, None), poi("Yela", "B", 0.0, None)];
+        assert!(propose_merges(&database(&pois), &all_custom(&pois), 0.5).is_empty());
+    }
+
+    #[test]
+    fn imported_poi_is_canonical_and_kept() {
+        let pois = [
+            poi("Daymar", "Outpost", 0.0, None),
+            poi("Daymar", "My outpost", 0.1, Some(5)),
+            poi("Daymar", "Again outpost", 0.2, None),
+        ];
+        let database = database(&pois);
+        let proposals = propose_merges(&database, &all_custom(&pois[1..]), 0.5);
+        assert_eq!(proposals.len(), 1);
+        let proposal = &proposals[0];
+        assert_eq!(proposal.canonical, "Outpost");
+        assert_eq!(proposal.members[0].name, "Outpost");
+        // Imported one stays untouched, only custom ones go
+        assert_eq!(proposal.merged(&database), None);
+        let replaced: Vec<&str> = proposal.replaced().map(|p| p.name.as_str()).collect();
+        assert_eq!(replaced, vec!["My outpost", "Again outpost"]);
+
+        // Custom canonical picked by user replace custom ones only
+        let mut proposal = proposal.clone();
+        proposal.canonical = "My outpost".to_string();
+        assert_eq!(proposal.merged(&database).unwrap().name, "My outpost");
+        assert_eq!(proposal.replaced().count(), 2);
+    }
+
+    #[test]
+    fn imported_only_groups_are_skipped() {
+        let pois = [
+            poi("Daymar", "Imported", 0.0, None),
+            poi("Daymar", "Other imported", 0.1, None),
+            poi("Yela", "Imported", 0.0, None),
+            poi("Yela", "Custom", 0.1, None),
+        ];
+        assert!(propose_merges(&database(&pois), &HashSet::new(), 0.5).is_empty());
+
+        // Custom key is (container, name), Daymar Imported is not custom
+        let custom = all_custom(&pois[3..]);
+        let proposals = propose_merges(&database(&pois), &custom, 0.5);
+        assert_eq!(proposals.len(), 1);
+        assert_eq!(proposals[0].container, "Yela");
+        assert_eq!(proposals[0].canonical, "Imported");
+
+        let custom = all_custom(&[poi("Yela", "Other imported", 0.1, None)]);
+        assert!(propose_merges(&database(&pois), &custom, 0.5).is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+// Frames:
+//  - absolute: system coordinates, in km
+//  - local: container coordinates, centered and rotating with it, in km
+//  - geodetic: latitude & longitude in radian, altitude in km above container sea level
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geodetic {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+}
+
+impl Geodetic {
+    /// No geodetic coordinates, in Space
+    pub const UNDEFINED: Geodetic = Geodetic {
+        latitude: f64::NAN,
+        longitude: f64::NAN,
+        altitude: f64::NAN,
+    };
+
+    /// Geodetic to local, sea level is container body radius
+    pub fn to_local(self, sea_level: f64) -> Vec3d {
+        let radius = sea_level + self.altitude;
+        Vec3d {
+            x: -radius * self.latitude.cos() * self.longitude.sin(),
+            y: radius * self.latitude.cos() * self.longitude.cos(),
+            z: radius * self.latitude.sin(),
+        }
+    }
+}
+
+impl Vec3d {
+    /// Absolute to local at time
+    pub fn transform_to_local(&self, time_elapsed: f64, container: &Container) -> Vec3d {
+        let rotation_state_in_degrees = container.rotation_state(time_elapsed);
+        (*self - container.coordinates).rotate((-rotation_state_in_degrees).to_radians())
+    }
+
+    /// Local to absolute at time
+    pub fn transform_to_absolute(&self, time_elapsed: f64, container: &Container) -> Vec3d {
+        let rotation_state_in_degrees = container.rotation_state(time_elapsed);
+        self.rotate(rotation_state_in_degrees.to_radians()) + container.coordinates
+    }
+
+    /// Local to geodetic, sea level is container body radius
+    pub fn to_geodetic(self, sea_level: f64) -> Geodetic {
+        Geodetic {
+            latitude: self.latitude(),
+            longitude: self.longitude(),
+            altitude: self.altitude(sea_level),
+        }
+    }
+}
+
+impl Container {
+    /// Geodetic of local coordinates, undefined in Space
+    pub fn local_to_geodetic(&self, local_coordinates: Vec3d) -> Geodetic {
+        if self.name == "Space" {
+            Geodetic::UNDEFINED
+        } else {
+            local_coordinates.to_geodetic(self.radius_body)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    const SAMPLES: usize = 10_000;
+
+    fn random_container(rng: &mut StdRng) -> Container {
+        Container {
+            name: "Test".to_string(),
+            coordinates: random_vec3d(rng, 5e7),
+            radius_body: rng.gen_range(50.0..2000.0),
+            // Some containers do not rotate
+            rotation_speed: if rng.gen_bool(0.2) {
+                0.0
+            } else {
+                rng.gen_range(1.0..30.0)
+            },
+            rotation_adjust: rng.gen_range(0.0..360.0),
+            ..Default::default()
+        }
+    }
+
+    fn random_vec3d(rng: &mut StdRng, range: f64) -> Vec3d {
+        Vec3d::new(
+            rng.gen_range(-range..range),
+            rng.gen_range(-range..range),
+            rng.gen_range(-range..range),
+        )
+    }
+
+    fn random_geodetic(rng: &mut StdRng) -> Geodetic {
+        Geodetic {
+            // Avoid poles where longitude is undefined
+            latitude: rng.gen_range(-FRAC_PI_2 + 1e-3..FRAC_PI_2 - 1e-3),
+            longitude: rng.gen_range(-PI..PI),
+            altitude: rng.gen_range(-10.0..100.0),
+        }
+    }
+
+    fn assert_close(a: Vec3d, b: Vec3d, tolerance: f64) {
+        assert!((a - b).norm() <= tolerance, "{a:?} != {b:?}");
+    }
+
+    fn assert_geodetic_close(a: Geodetic, b: Geodetic) {
+        let longitude = (a.longitude - b.longitude + 3.0 * PI).rem_euclid(2.0 * PI) - PI;
+        assert!((a.latitude - b.latitude).abs() < 1e-9, "{a:?} != {b:?}");
+        // Longitude lose precision near poles
+        assert!(
+            (longitude * a.latitude.cos()).abs() < 1e-9,
+            "{a:?} != {b:?}"
+        );
+        assert!((a.altitude - b.altitude).abs() < 1e-6, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn absolute_local_round_trip() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..SAMPLES {
+            let container = random_container(&mut rng);
+            let time_elapsed = rng.gen_range(0.0..1e9);
+            let absolute = container.coordinates + random_vec3d(&mut rng, 5000.0);
+
+            let local = absolute.transform_to_local(time_elapsed, &container);
+            assert_close(
+                local.transform_to_absolute(time_elapsed, &container),
+                absolute,
+                1e-6,
+            );
+            // Rotation keep distance to container center
+            assert!(((absolute - container.coordinates).norm() - local.norm()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn local_geodetic_round_trip() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..SAMPLES {
+            let sea_level = rng.gen_range(50.0..2000.0);
+            let geodetic = random_geodetic(&mut rng);
+
+            let local = geodetic.to_local(sea_level);
+            assert_geodetic_close(local.to_geodetic(sea_level), geodetic);
+            assert_close(
+                local.to_geodetic(sea_level).to_local(sea_level),
+                local,
+                1e-6,
+            );
+        }
+    }
+
+    #[test]
+    fn absolute_geodetic_round_trip() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..SAMPLES {
+            let container = random_container(&mut rng);
+            let time_elapsed = rng.gen_range(0.0..1e9);
+            let geodetic = random_geodetic(&mut rng);
+
+            let absolute = geodetic
+                .to_local(container.radius_body)
+                .transform_to_absolute(time_elapsed, &container);
+            assert_geodetic_close(
+                container.local_to_geodetic(absolute.transform_to_local(time_elapsed, &container)),
+                geodetic,
+            );
+        }
+    }
+
+    #[test]
+    fn geodetic_conventions() {
+        let sea_level = 1000.0;
+        let north_pole = Geodetic {
+            latitude: FRAC_PI_2,
+            longitude: 0.0,
+            altitude: 0.0,
+        };
+        assert_close(
+            north_pole.to_local(sea_level),
+            Vec3d::new(0.0, 0.0, sea_level),
+            1e-9,
+        );
+        // Longitude grow toward -x
+        let east = Geodetic {
+            latitude: 0.0,
+            longitude: FRAC_PI_2,
+            altitude: 10.0,
+        };
+        assert_close(
+            east.to_local(sea_level),
+            Vec3d::new(-1010.0, 0.0, 0.0),
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn space_has_no_geodetic() {
+        let space = Container {
+            name: "Space".to_string(),
+            ..Default::default()
+        };
+        let geodetic = space.local_to_geodetic(Vec3d::new(1.0, 2.0, 3.0));
+        assert!(geodetic.latitude.is_nan() & geodetic.longitude.is_nan());
+    }
+}
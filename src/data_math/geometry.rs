@@ -0,0 +1,487 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use std::f64::consts::PI;
+
+/// Anything we can look at from an observer
+#[derive(Debug, Clone, Copy)]
+pub enum Destination<'a> {
+    Poi(&'a Poi),
+    Position(&'a ProcessedPosition),
+}
+
+impl Destination<'_> {
+    /// Container name and coordinates in this container frame
+    pub fn local(&self) -> (&str, Vec3d) {
+        match self {
+            Destination::Poi(poi) => (&poi.container, poi.coordinates),
+            Destination::Position(position) => {
+                (&position.container_name, position.local_coordinates)
+            }
+        }
+    }
+}
+
+/// Frame in which a relative geometry is expressed
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Frame {
+    /// Observer and destination in the same rotating container
+    Local(String),
+    /// Destination in another container, seen from observer container
+    Crossed(String, String),
+    /// Observer in space, only absolute coordinates make sense
+    #[default]
+    Absolute,
+}
+
+impl Frame {
+    pub fn description(&self) -> String {
+        match self {
+            Frame::Local(container) => format!("{container} local"),
+            Frame::Crossed(observer, destination) => format!("{destination} from {observer}"),
+            Frame::Absolute => "Absolute".to_string(),
+        }
+    }
+}
+
+/// Geometry between an observer and a destination at a given time
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelativeGeometry {
+    /// Straight line distance in km
+    pub distance: f64,
+    /// Great circle distance at sea level in km, only in same container
+    pub surface_distance: f64,
+    /// Initial great circle heading in radian, from north clockwise
+    pub bearing: f64,
+    /// Angle above observer horizontal plane in radian
+    pub elevation: f64,
+    /// Body hiding destination, None if in line of sight
+    pub occluded_by: Option<String>,
+    pub frame: Frame,
+    /// ± on distance from capture uncertainty, in km
+    pub distance_error: f64,
+    /// ± on bearing from capture uncertainty, in radian
+    pub bearing_error: f64,
+}
+
+impl RelativeGeometry {
+    /// Propagate uncertainty radius (km) of both ends
+    pub fn with_uncertainty(mut self, observer: f64, destination: f64) -> Self {
+        self.distance_error = observer + destination;
+        let horizontal = if self.surface_distance.is_finite() {
+            self.surface_distance
+        } else {
+            self.distance
+        };
+        self.bearing_error = if self.bearing.is_finite() {
+            self.distance_error.atan2(horizontal)
+        } else {
+            f64::NAN
+        };
+        self
+    }
+}
+
+impl Default for RelativeGeometry {
+    fn default() -> Self {
+        Self {
+            distance: f64::NAN,
+            surface_distance: f64::NAN,
+            bearing: f64::NAN,
+            elevation: f64::NAN,
+            occluded_by: None,
+            frame: Frame::default(),
+            distance_error: f64::NAN,
+            bearing_error: f64::NAN,
+        }
+    }
+}
+
+/// First body crossed by straight line between a and b, endpoints may lie on a body surface
+pub fn line_obstruction(a: Vec3d, b: Vec3d, database: &Database) -> Option<String> {
+    let ab = b - a;
+    let len2 = ab.dot_product(&ab);
+    database
+        .values()
+        .filter(|c| c.radius_body > 0.0)
+        .find(|c| {
+            let t = if len2 == 0.0 {
+                0.0
+            } else {
+                (c.coordinates - a).dot_product(&ab) / len2
+            };
+            (t > 0.0) & (t < 1.0) & ((a + ab * t - c.coordinates).norm() < c.radius_body)
+        })
+        .map(|c| c.name.clone())
+}
+
+/// Geometry from observer to destination, at observer time
+///
+/// Destination is moved to observer time with its container rotation, then expressed in observer
+/// container frame. Observer in Space only get distance.
+pub fn relative_geometry(
+    observer: &ProcessedPosition,
+    destination: Destination,
+    database: &Database,
+) -> Option<RelativeGeometry> {
+    let time_elapsed = observer.time_elapsed;
+    let observer_container = database.get(&observer.container_name)?;
+    let (destination_container_name, destination_local) = destination.local();
+    let destination_container = database.get(destination_container_name)?;
+
+    // Destination in observer container frame
+    let frame = if observer_container.name == "Space" {
+        Frame::Absolute
+    } else if observer_container.name == destination_container.name {
+        Frame::Local(observer_container.name.clone())
+    } else {
+        Frame::Crossed(
+            observer_container.name.clone(),
+            destination_container.name.clone(),
+        )
+    };
+    let destination_local = if observer_container.name == destination_container.name {
+        destination_local
+    } else {
+        destination_local
+            .transform_to_absolute(time_elapsed, destination_container)
+            .transform_to_local(time_elapsed, observer_container)
+    };
+
+    let origin = observer.local_coordinates;
+    let delta = destination_local - origin;
+    let distance = delta.norm();
+
+    // Any body, including ours, may hide destination
+    let occluded_by = line_obstruction(
+        origin.transform_to_absolute(time_elapsed, observer_container),
+        destination_local.transform_to_absolute(time_elapsed, observer_container),
+        database,
+    );
+
+    if frame == Frame::Absolute {
+        return Some(RelativeGeometry {
+            distance,
+            occluded_by,
+            frame,
+            ..Default::default()
+        });
+    }
+
+    let surface_distance = match frame {
+        Frame::Local(_) => {
+            observer_container.radius_body * origin.angle_with(&destination_local).to_radians()
+        }
+        _ => f64::NAN,
+    };
+    let bearing = (origin.loxodromie_to(destination_local) + 2.0 * PI) % (2.0 * PI);
+    let elevation = (delta.dot_product(&origin) / (distance * origin.norm()))
+        .clamp(-1.0, 1.0)
+        .asin();
+
+    Some(RelativeGeometry {
+        distance,
+        surface_distance,
+        bearing,
+        elevation,
+        occluded_by,
+        frame,
+        distance_error: f64::NAN,
+        bearing_error: f64::NAN,
+    })
+}
+
+/// Angle between destination and a reference body, as seen from observer
+#[derive(Debug, Clone, PartialEq)]
+pub struct AngularReference {
+    pub name: String,
+    /// Angular separation in degrees
+    pub separation: f64,
+    /// Distance from observer to reference in km
+    pub distance: f64,
+}
+
+/// Separation of destination from the star and the nearest bodies, to aim in space
+pub fn angular_references(
+    observer: &ProcessedPosition,
+    destination: Destination,
+    database: &Database,
+    count: usize,
+) -> Vec<AngularReference> {
+    let time_elapsed = observer.time_elapsed;
+    let Some(observer_container) = database.get(&observer.container_name) else {
+        return Vec::new();
+    };
+    let (destination_container_name, destination_local) = destination.local();
+    let Some(destination_container) = database.get(destination_container_name) else {
+        return Vec::new();
+    };
+    let origin = observer
+        .local_coordinates
+        .transform_to_absolute(time_elapsed, observer_container);
+    let direction =
+        destination_local.transform_to_absolute(time_elapsed, destination_container) - origin;
+
+    let reference = |container: &Container| {
+        let to_reference = container.coordinates - origin;
+        AngularReference {
+            name: container.name.clone(),
+            separation: direction.angle_with(&to_reference),
+            distance: to_reference.norm(),
+        }
+    };
+
+    // Nearest bodies, the star always come first
+    let mut bodies: Vec<AngularReference> = database
+        .values()
+        .filter(|c| (c.radius_body > 0.0) & (c.name != "Stanton"))
+        .map(reference)
+        .collect();
+    bodies.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    bodies.truncate(count);
+
+    database
+        .get("Stanton")
+        .map(reference)
+        .into_iter()
+        .chain(bodies)
+        .collect()
+}
+
+/// Glide path from observer down to a surface destination
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DescentGuidance {
+    /// Heading to fly, in radian
+    pub bearing: f64,
+    /// Angle below horizon of straight glide, in radian
+    pub descent_angle: f64,
+    /// Distance between sub points at sea level, in km
+    pub horizontal_distance: f64,
+    /// Observer altitude above sea level, in km
+    pub altitude: f64,
+    pub altitude_to_lose: f64,
+}
+
+/// Descent from observer to destination, in destination container frame at observer time
+pub fn descent_guidance(
+    observer: &ProcessedPosition,
+    destination: Destination,
+    database: &Database,
+) -> Option<DescentGuidance> {
+    let time_elapsed = observer.time_elapsed;
+    let observer_container = database.get(&observer.container_name)?;
+    let (container_name, destination_local) = destination.local();
+    let container = database.get(container_name)?;
+    if container.name == "Space" {
+        return None;
+    }
+
+    let origin = observer
+        .local_coordinates
+        .transform_to_absolute(time_elapsed, observer_container)
+        .transform_to_local(time_elapsed, container);
+    let altitude = origin.altitude(container.radius_body);
+    let altitude_to_lose = altitude - destination_local.altitude(container.radius_body);
+    let horizontal_distance =
+        great_circle_distance(origin, destination_local, container.radius_body);
+
+    Some(DescentGuidance {
+        bearing: (origin.loxodromie_to(destination_local) + 2.0 * PI) % (2.0 * PI),
+        descent_angle: altitude_to_lose.atan2(horizontal_distance),
+        horizontal_distance,
+        altitude,
+        altitude_to_lose,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(
+        name: &str,
+        coordinates: Vec3d,
+        radius_body: f64,
+        rotation_speed: f64,
+    ) -> Container {
+        Container {
+            name: name.to_string(),
+            coordinates,
+            radius_body,
+            rotation_speed,
+            rotation_adjust: 30.0,
+            ..Default::default()
+        }
+    }
+
+    fn database() -> Database {
+        [
+            Container {
+                name: "Space".to_string(),
+                ..Default::default()
+            },
+            container("Daymar", Vec3d::new(10_000.0, 0.0, 0.0), 100.0, 3.0),
+            // Does not rotate
+            container("Yela", Vec3d::new(0.0, 20_000.0, 0.0), 200.0, 0.0),
+        ]
+        .into_iter()
+        .map(|c| (c.name.clone(), c))
+        .collect()
+    }
+
+    fn geodetic(latitude: f64, longitude: f64, altitude: f64) -> Geodetic {
+        Geodetic {
+            latitude: latitude.to_radians(),
+            longitude: longitude.to_radians(),
+            altitude,
+        }
+    }
+
+    fn observer(container: &str, local_coordinates: Vec3d, time_elapsed: f64) -> ProcessedPosition {
+        ProcessedPosition {
+            local_coordinates,
+            time_elapsed,
+            container_name: container.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn poi(container: &str, coordinates: Vec3d) -> Poi {
+        Poi {
+            name: "Target".to_string(),
+            container: container.to_string(),
+            coordinates,
+            ..Default::default()
+        }
+    }
+
+    fn absolute(database: &Database, poi: &Poi, time_elapsed: f64) -> Vec3d {
+        poi.coordinates
+            .transform_to_absolute(time_elapsed, &database[&poi.container])
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn local_frame_below_horizon() {
+        let database = database();
+        let observer = observer("Daymar", geodetic(0.0, 0.0, 0.0).to_local(100.0), 1000.0);
+        let target = poi("Daymar", geodetic(0.0, 10.0, 0.0).to_local(100.0));
+
+        let geometry = relative_geometry(&observer, Destination::Poi(&target), &database).unwrap();
+        assert_eq!(geometry.frame, Frame::Local("Daymar".to_string()));
+        assert_close(geometry.distance, 200.0 * 5.0_f64.to_radians().sin());
+        assert_close(geometry.surface_distance, 100.0 * 10.0_f64.to_radians());
+        // Due east
+        assert_close(geometry.bearing, PI / 2.0);
+        // Chord dives under horizon by half the arc, through the body
+        assert_close(geometry.elevation, -5.0_f64.to_radians());
+        assert_eq!(geometry.occluded_by, Some("Daymar".to_string()));
+    }
+
+    #[test]
+    fn local_frame_straight_up() {
+        let database = database();
+        let observer = observer("Daymar", geodetic(20.0, 40.0, 0.0).to_local(100.0), 1000.0);
+        let target = poi("Daymar", geodetic(20.0, 40.0, 10.0).to_local(100.0));
+
+        let geometry = relative_geometry(&observer, Destination::Poi(&target), &database).unwrap();
+        assert_close(geometry.distance, 10.0);
+        assert_close(geometry.surface_distance, 0.0);
+        assert_close(geometry.elevation, PI / 2.0);
+        assert_eq!(geometry.occluded_by, None);
+    }
+
+    #[test]
+    fn crossed_frame_match_absolute_distance() {
+        let database = database();
+        let observer_local = geodetic(0.0, 0.0, 1.0).to_local(100.0);
+        let target_local = geodetic(10.0, 50.0, 0.0).to_local(200.0);
+        let target = poi("Yela", target_local);
+
+        for time_elapsed in [0.0, 5000.0] {
+            let observer = observer("Daymar", observer_local, time_elapsed);
+            let geometry =
+                relative_geometry(&observer, Destination::Poi(&target), &database).unwrap();
+            assert_eq!(
+                geometry.frame,
+                Frame::Crossed("Daymar".to_string(), "Yela".to_string())
+            );
+            let expected = (target_local.transform_to_absolute(time_elapsed, &database["Yela"])
+                - observer_local.transform_to_absolute(time_elapsed, &database["Daymar"]))
+            .norm();
+            assert_close(geometry.distance, expected);
+            assert!(geometry.surface_distance.is_nan());
+            assert!(geometry.bearing.is_finite());
+        }
+    }
+
+    #[test]
+    fn non_rotating_container_is_fixed_in_time() {
+        let database = database();
+        let target = poi("Yela", geodetic(0.0, 10.0, 0.0).to_local(200.0));
+        let observer_local = geodetic(0.0, 0.0, 5.0).to_local(200.0);
+
+        let at = |time_elapsed| {
+            relative_geometry(
+                &observer("Yela", observer_local, time_elapsed),
+                Destination::Poi(&target),
+                &database,
+            )
+            .unwrap()
+        };
+        let (before, after) = (at(0.0), at(100_000.0));
+        assert_eq!(before.distance, after.distance);
+        assert_eq!(before.bearing, after.bearing);
+        assert_eq!(before.elevation, after.elevation);
+        // Seen from space, Yela does not move either
+        assert_eq!(
+            absolute(&database, &target, 0.0),
+            absolute(&database, &target, 100_000.0)
+        );
+    }
+
+    #[test]
+    fn absolute_frame_from_space() {
+        let database = database();
+        let observer_absolute = Vec3d::new(0.0, 19_000.0, 0.0);
+        let observer = observer("Space", observer_absolute, 0.0);
+        let target = poi("Yela", geodetic(0.0, 0.0, 0.0).to_local(200.0));
+
+        let geometry = relative_geometry(&observer, Destination::Poi(&target), &database).unwrap();
+        assert_eq!(geometry.frame, Frame::Absolute);
+        let expected = (absolute(&database, &target, 0.0) - observer_absolute).norm();
+        assert_close(geometry.distance, expected);
+        assert!(geometry.bearing.is_nan());
+        assert!(geometry.elevation.is_nan());
+    }
+
+    #[test]
+    fn unknown_container() {
+        let database = database();
+        let observer = observer("Nowhere", Vec3d::default(), 0.0);
+        let target = poi("Yela", Vec3d::new(200.0, 0.0, 0.0));
+        assert_eq!(
+            relative_geometry(&observer, Destination::Poi(&target), &database),
+            None
+        );
+    }
+}
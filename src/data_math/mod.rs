@@ -19,16 +19,41 @@ use chrono::Utc;
 use egui::Color32;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use std::ops::{Add, Sub};
+use std::ops::{Add, Mul, Sub};
 
 use crate::Database;
 
+mod calibration;
+mod dedupe;
+mod frames;
+mod geometry;
+mod path_tools;
+mod prediction;
+mod quantum;
+mod refine;
+mod route;
+pub use calibration::*;
+pub use dedupe::*;
+pub use frames::*;
+pub use geometry::*;
+pub use path_tools::*;
+pub use prediction::*;
+pub use quantum::*;
+pub use refine::*;
+pub use route::*;
+
 #[derive(Debug, Default, Copy, Clone, PartialEq, Deserialize, Serialize)]
 pub struct SpaceTimePosition {
     pub coordinates: Vec3d,
     pub timestamp: chrono::DateTime<Utc>,
+    /// Quantization step of captured coordinates in km, 0 if unknown
+    #[serde(default)]
+    pub precision: f64,
 }
 
+/// Quantization step assumed for captures without known precision, in km
+pub const DEFAULT_CAPTURE_PRECISION: f64 = 0.001;
+
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct ProcessedPosition {
     pub space_time_position: SpaceTimePosition,
@@ -42,6 +67,101 @@ pub struct ProcessedPosition {
     pub color: Option<Color32>,
 }
 
+impl ProcessedPosition {
+    /// Process a raw space time position against the database
+    pub fn new(
+        space_time_position: SpaceTimePosition,
+        time_elapsed: f64,
+        name: String,
+        database: &Database,
+    ) -> Self {
+        let container = get_current_container(&space_time_position.coordinates, database);
+        let local_coordinates = space_time_position
+            .coordinates
+            .transform_to_local(time_elapsed, &container);
+        let geodetic = container.local_to_geodetic(local_coordinates);
+
+        ProcessedPosition {
+            space_time_position,
+            local_coordinates,
+            time_elapsed,
+            container_name: container.name,
+            name,
+            latitude: geodetic.latitude,
+            longitude: geodetic.longitude,
+            altitude: geodetic.altitude,
+            color: None,
+        }
+    }
+
+    /// Map point and POI have no absolute coordinates, only capture can be processed again
+    pub fn is_capture(&self) -> bool {
+        self.space_time_position.coordinates != Vec3d::default()
+    }
+
+    /// Radius in km around local coordinates where the capture really was
+    ///
+    /// Sum of coordinates quantization and of container rotation during capture latency (s).
+    pub fn uncertainty(&self, database: &Database, latency: f64) -> f64 {
+        if !self.is_capture() {
+            return 0.0;
+        }
+        let precision = if self.space_time_position.precision > 0.0 {
+            self.space_time_position.precision
+        } else {
+            DEFAULT_CAPTURE_PRECISION
+        };
+        let quantization = precision * 3.0_f64.sqrt() / 2.0;
+        let rotation = database
+            .get(&self.container_name)
+            .map(|container| {
+                container
+                    .rotation_speed_in_degrees_per_second()
+                    .to_radians()
+                    * self.local_coordinates.x.hypot(self.local_coordinates.y)
+                    * latency
+            })
+            .unwrap_or(0.0);
+        quantization + rotation
+    }
+
+    /// Recompute all derived fields from raw space time position, keep name and color
+    pub fn reprocess(&mut self, time_elapsed: f64, database: &Database) -> Option<ReprocessDiff> {
+        if !self.is_capture() {
+            return None;
+        }
+        let before = self.clone();
+        *self = ProcessedPosition::new(
+            self.space_time_position,
+            time_elapsed,
+            std::mem::take(&mut self.name),
+            database,
+        );
+        self.color = before.color;
+
+        Some(ReprocessDiff {
+            name: self.name.clone(),
+            container_before: before.container_name.clone(),
+            container_after: self.container_name.clone(),
+            moved: if before.container_name == self.container_name {
+                (self.local_coordinates - before.local_coordinates).norm()
+            } else {
+                f64::NAN
+            },
+        })
+    }
+}
+
+/// How a position changed when processed again
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReprocessDiff {
+    pub name: String,
+    pub container_before: String,
+    pub container_after: String,
+    /// Distance in km between old and new local coordinates, NaN if container changed
+    pub moved: f64,
+}
+
 #[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Default)]
 pub struct Vec3d {
     pub x: f64,
@@ -71,6 +191,17 @@ impl Sub for Vec3d {
     }
 }
 
+impl Mul<f64> for Vec3d {
+    type Output = Self;
+    fn mul(self, other: f64) -> Self {
+        Self {
+            x: self.x * other,
+            y: self.y * other,
+            z: self.z * other,
+        }
+    }
+}
+
 impl Vec3d {
     pub fn new(x: f64, y: f64, z: f64) -> Vec3d {
         Vec3d { x, y, z }
@@ -78,11 +209,13 @@ impl Vec3d {
     pub fn norm(&self) -> f64 {
         (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
     }
-    fn dot_product(&self, v: &Vec3d) -> f64 {
+    pub fn dot_product(&self, v: &Vec3d) -> f64 {
         self.x * v.x + self.y * v.y + self.z * v.z
     }
-    fn angle_with(&self, v: &Vec3d) -> f64 {
-        (self.dot_product(v) / (self.norm() + v.norm()))
+    /// Angle between vectors, in degrees
+    pub fn angle_with(&self, v: &Vec3d) -> f64 {
+        (self.dot_product(v) / (self.norm() * v.norm()))
+            .clamp(-1.0, 1.0)
             .acos()
             .to_degrees()
     }
@@ -99,20 +232,12 @@ impl Vec3d {
         (self.z / self.norm()).asin()
     }
     pub fn longitude(&self) -> f64 {
-        self.x.atan2(self.y) * -1.0
+        -self.x.atan2(self.y)
     }
     pub fn altitude(&self, sea_level: f64) -> f64 {
         self.norm() - sea_level
     }
 
-    pub fn transform_to_local(&self, time_elapsed: f64, container: &Container) -> Vec3d {
-        let rotation_speed_in_degrees_per_second = 0.1 * (1.0 / container.rotation_speed);
-        let rotation_state_in_degrees = (rotation_speed_in_degrees_per_second * time_elapsed
-            + container.rotation_adjust)
-            % 360.0;
-        (*self - container.coordinates).rotate((-rotation_state_in_degrees).to_radians())
-    }
-
     pub fn loxodromie_to(&self, target: Vec3d) -> f64 {
         // let a = ((target.longitude().to_degrees() - self.longitude().to_degrees())
         // / ((PI / 4.0 + self.latitude() / 2.0).tan().ln()
@@ -160,6 +285,23 @@ pub struct Container {
     pub grid_radius: f64,
     pub poi: BTreeMap<String, Poi>,
 }
+
+impl Container {
+    /// Rotation speed in degrees per second, from hours per rotation (0 mean no rotation)
+    pub fn rotation_speed_in_degrees_per_second(&self) -> f64 {
+        if self.rotation_speed == 0.0 {
+            0.0
+        } else {
+            0.1 / self.rotation_speed
+        }
+    }
+
+    /// Rotation state in degrees at given time elapsed since reference time
+    pub fn rotation_state(&self, time_elapsed: f64) -> f64 {
+        (self.rotation_speed_in_degrees_per_second() * time_elapsed + self.rotation_adjust) % 360.0
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
 pub struct Poi {
     pub name: String,
@@ -170,19 +312,82 @@ pub struct Poi {
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
     pub altitude: Option<f64>,
+    /// Number of captures averaged into this POI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<usize>,
+    /// RMS distance of averaged captures to POI, in km
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<f64>,
+}
+
+/// Seconds elapsed between reference time and timestamp, corrected by clock offset
+pub fn time_elapsed(
+    timestamp: chrono::DateTime<Utc>,
+    reference_time: chrono::DateTime<Utc>,
+    clock_offset: f64,
+) -> f64 {
+    (timestamp - reference_time).num_nanoseconds().unwrap() as f64 / 1e9 + clock_offset
+}
+
+/// Process again a set of positions against database, report how each capture moved
+pub fn reprocess_positions<'a>(
+    positions: impl IntoIterator<Item = &'a mut ProcessedPosition>,
+    database: &Database,
+    reference_time: chrono::DateTime<Utc>,
+    clock_offset: f64,
+) -> Vec<ReprocessDiff> {
+    positions
+        .into_iter()
+        .filter_map(|position| {
+            let time_elapsed = time_elapsed(
+                position.space_time_position.timestamp,
+                reference_time,
+                clock_offset,
+            );
+            position.reprocess(time_elapsed, database)
+        })
+        .collect()
+}
+
+/// Poi at a position, in its container or in Space with absolute coordinates
+pub fn processed_point_to_poi(position: &ProcessedPosition) -> Poi {
+    let (container, coordinates) =
+        if (position.container_name == "Space") | (position.container_name.is_empty()) {
+            (
+                "Space".to_string(),
+                position.space_time_position.coordinates,
+            )
+        } else {
+            (position.container_name.clone(), position.local_coordinates)
+        };
+    Poi {
+        name: position.name.clone(),
+        container,
+        coordinates,
+        quaternions: None,
+        marker: None,
+        latitude: Some(position.latitude),
+        longitude: Some(position.longitude),
+        altitude: Some(position.altitude),
+        samples: None,
+        spread: None,
+    }
 }
 
 pub fn poi_to_processed_point(p: &Poi, database: &Database) -> ProcessedPosition {
-    let sea_level = database.get(&p.container).unwrap().radius_body;
+    let geodetic = database
+        .get(&p.container)
+        .unwrap()
+        .local_to_geodetic(p.coordinates);
     ProcessedPosition {
         space_time_position: SpaceTimePosition::default(),
         local_coordinates: p.coordinates,
         time_elapsed: 0.0,
         container_name: p.container.to_string(),
         name: p.name.to_string(),
-        latitude: p.coordinates.latitude(),
-        longitude: p.coordinates.longitude(),
-        altitude: p.coordinates.altitude(sea_level),
+        latitude: geodetic.latitude,
+        longitude: geodetic.longitude,
+        altitude: geodetic.altitude,
         color: None,
     }
 }
@@ -0,0 +1,507 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use chrono::Duration;
+use uuid::Uuid;
+
+/// Chord between two points in km, in container frame or in absolute space on container transition
+///
+/// Transition with a point without absolute coordinates (map point, POI) cannot be measured and count as 0.
+pub fn segment_length(a: &ProcessedPosition, b: &ProcessedPosition) -> f64 {
+    if a.container_name == b.container_name {
+        (a.local_coordinates - b.local_coordinates).norm()
+    } else if a.is_capture() & b.is_capture() {
+        (a.space_time_position.coordinates - b.space_time_position.coordinates).norm()
+    } else {
+        0.0
+    }
+}
+
+/// Sum of chords between consecutive points, in km
+pub fn path_length(history: &[ProcessedPosition]) -> f64 {
+    history
+        .windows(2)
+        .map(|w| segment_length(&w[0], &w[1]))
+        .sum()
+}
+
+/// Part of a path in a single container
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathSegment {
+    pub container_name: String,
+    /// Index of first point
+    pub start: usize,
+    pub count: usize,
+    /// Length inside container, in km
+    pub length: f64,
+}
+
+/// Split path in segments of consecutive points in the same container
+pub fn path_segments(history: &[ProcessedPosition]) -> Vec<PathSegment> {
+    let mut start = 0;
+    container_runs(history)
+        .into_iter()
+        .map(|run| {
+            let segment = PathSegment {
+                container_name: run[0].container_name.clone(),
+                start,
+                count: run.len(),
+                length: path_length(run),
+            };
+            start += run.len();
+            segment
+        })
+        .collect()
+}
+
+/// Cumulative length at each point of path, in km
+pub fn cumulative_distance(history: &[ProcessedPosition]) -> Vec<f64> {
+    let mut total = 0.0;
+    let mut cumulative = vec![0.0];
+    for w in history.windows(2) {
+        total += segment_length(&w[0], &w[1]);
+        cumulative.push(total);
+    }
+    cumulative.truncate(history.len());
+    cumulative
+}
+
+/// Seconds since first point of path, for each point
+pub fn cumulative_time(history: &[ProcessedPosition]) -> Vec<f64> {
+    history
+        .iter()
+        .map(|p| {
+            (p.space_time_position.timestamp - history[0].space_time_position.timestamp)
+                .num_milliseconds() as f64
+                / 1000.0
+        })
+        .collect()
+}
+
+/// Seconds of travel at each point, from absolute time between consecutive points
+///
+/// Unlike [`cumulative_time`] it never decrease, even on a reversed or merged path.
+pub fn monotonic_time(history: &[ProcessedPosition]) -> Vec<f64> {
+    let mut total = 0.0;
+    let mut times = vec![0.0];
+    for w in history.windows(2) {
+        total += (w[1].space_time_position.timestamp - w[0].space_time_position.timestamp)
+            .num_milliseconds()
+            .abs() as f64
+            / 1000.0;
+        times.push(total);
+    }
+    times.truncate(history.len());
+    times
+}
+
+/// Speed in m/s at each point, from previous point (NaN on first point)
+pub fn path_speeds(history: &[ProcessedPosition]) -> Vec<f64> {
+    let times = cumulative_time(history);
+    let mut speeds = vec![f64::NAN];
+    for (w, t) in history.windows(2).zip(times.windows(2)) {
+        let distance = segment_length(&w[0], &w[1]);
+        speeds.push(if t[1] > t[0] {
+            distance * 1000.0 / (t[1] - t[0])
+        } else {
+            f64::NAN
+        });
+    }
+    speeds.truncate(history.len());
+    speeds
+}
+
+/// Where a position project on a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathProgress {
+    /// Index of segment start point
+    pub segment: usize,
+    /// Distance along path from first point, in km
+    pub distance: f64,
+    /// Time along path from first point, in seconds
+    pub time: f64,
+    /// Distance between position and path, in km
+    pub cross_track: f64,
+}
+
+/// Project position on nearest segment of path, searching from segment `from` onward
+pub fn project_on_path(
+    history: &[ProcessedPosition],
+    position: &ProcessedPosition,
+    from: usize,
+) -> Option<PathProgress> {
+    let distances = cumulative_distance(history);
+    let times = cumulative_time(history);
+    let p = position.local_coordinates;
+
+    history
+        .windows(2)
+        .enumerate()
+        .skip(from)
+        .filter(|(_, w)| {
+            (w[0].container_name == position.container_name)
+                & (w[1].container_name == position.container_name)
+        })
+        .map(|(i, w)| {
+            let (a, b) = (w[0].local_coordinates, w[1].local_coordinates);
+            let t = segment_ratio(p, a, b);
+            PathProgress {
+                segment: i,
+                distance: distances[i] + (distances[i + 1] - distances[i]) * t,
+                time: times[i] + (times[i + 1] - times[i]) * t,
+                cross_track: (p - (a + (b - a) * t)).norm(),
+            }
+        })
+        .min_by(|a, b| a.cross_track.total_cmp(&b.cross_track))
+}
+
+/// Ratio of the projection of p on segment [a, b], 0 is a and 1 is b
+fn segment_ratio(p: Vec3d, a: Vec3d, b: Vec3d) -> f64 {
+    let ab = b - a;
+    let len2 = ab.x * ab.x + ab.y * ab.y + ab.z * ab.z;
+    if len2 == 0.0 {
+        return 0.0;
+    }
+    let ap = p - a;
+    ((ap.x * ab.x + ap.y * ab.y + ap.z * ab.z) / len2).clamp(0.0, 1.0)
+}
+
+/// Distance from p to segment [a, b]
+fn distance_to_segment(p: Vec3d, a: Vec3d, b: Vec3d) -> f64 {
+    (p - (a + (b - a) * segment_ratio(p, a, b))).norm()
+}
+
+/// Douglas–Peucker on a run of points in the same container, mark kept points
+fn douglas_peucker(run: &[ProcessedPosition], tolerance: f64, keep: &mut [bool]) {
+    if run.len() < 3 {
+        return;
+    }
+    let (a, b) = (
+        run[0].local_coordinates,
+        run[run.len() - 1].local_coordinates,
+    );
+    let (index, max) = run[1..run.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (i + 1, distance_to_segment(p.local_coordinates, a, b)))
+        .fold((0, 0.0), |acc, e| if e.1 > acc.1 { e } else { acc });
+    if max > tolerance {
+        keep[index] = true;
+        douglas_peucker(&run[..=index], tolerance, &mut keep[..=index]);
+        douglas_peucker(&run[index..], tolerance, &mut keep[index..]);
+    }
+}
+
+/// Split a path in runs of consecutive points in the same container
+fn container_runs(history: &[ProcessedPosition]) -> Vec<&[ProcessedPosition]> {
+    history
+        .chunk_by(|a, b| a.container_name == b.container_name)
+        .collect()
+}
+
+/// Douglas–Peucker simplification with tolerance in km, first and last point of each container are kept
+pub fn simplify_path(history: &[ProcessedPosition], tolerance: f64) -> Vec<ProcessedPosition> {
+    let mut simplified = Vec::new();
+    for run in container_runs(history) {
+        let mut keep = vec![false; run.len()];
+        keep[0] = true;
+        keep[run.len() - 1] = true;
+        douglas_peucker(run, tolerance, &mut keep);
+        simplified.extend(
+            run.iter()
+                .zip(keep)
+                .filter(|(_, keep)| *keep)
+                .map(|(p, _)| p.clone()),
+        );
+    }
+    simplified
+}
+
+/// Remove points closer than threshold (km) to the previous kept point
+pub fn remove_stationary(history: &[ProcessedPosition], threshold: f64) -> Vec<ProcessedPosition> {
+    let mut cleaned: Vec<ProcessedPosition> = Vec::new();
+    for point in history {
+        match cleaned.last() {
+            Some(last)
+                if (last.container_name == point.container_name)
+                    & ((last.local_coordinates - point.local_coordinates).norm() < threshold) => {}
+            _ => cleaned.push(point.clone()),
+        }
+    }
+    cleaned
+}
+
+/// New position between a and b (same container), ratio 0 is a and 1 is b
+pub fn interpolate(
+    a: &ProcessedPosition,
+    b: &ProcessedPosition,
+    ratio: f64,
+    database: &Database,
+) -> ProcessedPosition {
+    let local_coordinates =
+        a.local_coordinates + (b.local_coordinates - a.local_coordinates) * ratio;
+    // Map points and POIs have no absolute coordinates to interpolate
+    let coordinates = if a.is_capture() & b.is_capture() {
+        a.space_time_position.coordinates
+            + (b.space_time_position.coordinates - a.space_time_position.coordinates) * ratio
+    } else {
+        Vec3d::default()
+    };
+    let delta = b.space_time_position.timestamp - a.space_time_position.timestamp;
+    let timestamp = a.space_time_position.timestamp
+        + Duration::nanoseconds((delta.num_nanoseconds().unwrap_or(0) as f64 * ratio) as i64);
+
+    let geodetic = database
+        .get(&a.container_name)
+        .map(|container| container.local_to_geodetic(local_coordinates))
+        .unwrap_or(Geodetic::UNDEFINED);
+
+    ProcessedPosition {
+        space_time_position: SpaceTimePosition {
+            coordinates,
+            timestamp,
+            precision: a
+                .space_time_position
+                .precision
+                .max(b.space_time_position.precision),
+        },
+        local_coordinates,
+        time_elapsed: a.time_elapsed + (b.time_elapsed - a.time_elapsed) * ratio,
+        container_name: a.container_name.clone(),
+        name: "# ".to_owned() + &Uuid::new_v4().to_string()[9..18].to_uppercase(),
+        latitude: geodetic.latitude,
+        longitude: geodetic.longitude,
+        altitude: geodetic.altitude,
+        color: None,
+    }
+}
+
+/// Resample each container run with a point every step of measure, measure is cumulative on segments
+fn resample_path(
+    history: &[ProcessedPosition],
+    step: f64,
+    database: &Database,
+    measure: impl Fn(&ProcessedPosition, &ProcessedPosition) -> f64,
+) -> Vec<ProcessedPosition> {
+    if step <= 0.0 {
+        return history.to_vec();
+    }
+    let mut resampled = Vec::new();
+    for run in container_runs(history) {
+        resampled.push(run[0].clone());
+        // Measure left before next sample
+        let mut next = step;
+        for w in run.windows(2) {
+            let segment = measure(&w[0], &w[1]);
+            let mut done = 0.0;
+            while segment - done >= next {
+                done += next;
+                resampled.push(interpolate(&w[0], &w[1], done / segment, database));
+                next = step;
+            }
+            next -= segment - done;
+        }
+        // Always keep last point of run, unless last sample already fall on it
+        if (run.len() > 1) & (next < step) {
+            resampled.push(run[run.len() - 1].clone());
+        }
+    }
+    resampled
+}
+
+/// Resample path with a point every step km
+pub fn resample_path_distance(
+    history: &[ProcessedPosition],
+    step: f64,
+    database: &Database,
+) -> Vec<ProcessedPosition> {
+    resample_path(history, step, database, |a, b| {
+        (b.local_coordinates - a.local_coordinates).norm()
+    })
+}
+
+/// Resample path with a point every step seconds
+pub fn resample_path_time(
+    history: &[ProcessedPosition],
+    step: f64,
+    database: &Database,
+) -> Vec<ProcessedPosition> {
+    resample_path(history, step, database, |a, b| {
+        (b.space_time_position.timestamp - a.space_time_position.timestamp)
+            .num_milliseconds()
+            .max(0) as f64
+            / 1000.0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    fn point(container: &str, x: f64, y: f64, seconds: i64) -> ProcessedPosition {
+        ProcessedPosition {
+            space_time_position: SpaceTimePosition {
+                coordinates: Vec3d::new(x, y, 0.0),
+                timestamp: Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap(),
+                precision: 0.0,
+            },
+            local_coordinates: Vec3d::new(x, y, 0.0),
+            container_name: container.to_string(),
+            name: format!("{container} {x} {y}"),
+            ..Default::default()
+        }
+    }
+
+    fn line(count: usize) -> Vec<ProcessedPosition> {
+        (0..count)
+            .map(|i| point("A", i as f64, 0.0, i as i64))
+            .collect()
+    }
+
+    fn xs(history: &[ProcessedPosition]) -> Vec<f64> {
+        history.iter().map(|p| p.local_coordinates.x).collect()
+    }
+
+    #[test]
+    fn simplify_edge_cases() {
+        assert!(simplify_path(&[], 0.1).is_empty());
+        assert_eq!(simplify_path(&line(1), 0.1).len(), 1);
+        assert_eq!(simplify_path(&line(2), 0.1).len(), 2);
+    }
+
+    #[test]
+    fn simplify_straight_line_keeps_ends() {
+        assert_eq!(xs(&simplify_path(&line(10), 0.01)), vec![0.0, 9.0]);
+    }
+
+    #[test]
+    fn simplify_keeps_corner_over_tolerance() {
+        let history = vec![
+            point("A", 0.0, 0.0, 0),
+            point("A", 1.0, 0.51, 1),
+            point("A", 2.0, 1.0, 2),
+            point("A", 3.0, 0.51, 3),
+            point("A", 4.0, 0.0, 4),
+        ];
+        assert_eq!(xs(&simplify_path(&history, 0.1)), vec![0.0, 2.0, 4.0]);
+        // Everything is within a large tolerance
+        assert_eq!(xs(&simplify_path(&history, 2.0)), vec![0.0, 4.0]);
+    }
+
+    #[test]
+    fn simplify_keeps_container_transitions() {
+        let mut history = line(5);
+        history.extend((5..10).map(|i| point("B", i as f64, 0.0, i)));
+        let simplified = simplify_path(&history, 0.01);
+        assert_eq!(xs(&simplified), vec![0.0, 4.0, 5.0, 9.0]);
+    }
+
+    #[test]
+    fn remove_stationary_drops_duplicates() {
+        assert!(remove_stationary(&[], 0.1).is_empty());
+        let history = vec![
+            point("A", 0.0, 0.0, 0),
+            point("A", 0.0, 0.0, 1),
+            point("A", 0.05, 0.0, 2),
+            point("A", 1.0, 0.0, 3),
+            // Same place, other container, is not stationary
+            point("B", 1.0, 0.0, 4),
+        ];
+        let cleaned = remove_stationary(&history, 0.1);
+        assert_eq!(xs(&cleaned), vec![0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn resample_distance() {
+        let database = Database::new();
+        let history = vec![point("A", 0.0, 0.0, 0), point("A", 10.0, 0.0, 10)];
+
+        // Last sample fall on last point, which is not duplicated
+        let resampled = resample_path_distance(&history, 2.5, &database);
+        assert_eq!(xs(&resampled), vec![0.0, 2.5, 5.0, 7.5, 10.0]);
+
+        // Last point kept even when off step
+        let resampled = resample_path_distance(&history, 3.0, &database);
+        assert_eq!(xs(&resampled), vec![0.0, 3.0, 6.0, 9.0, 10.0]);
+
+        // Samples are evenly spaced across segments
+        let history = vec![
+            point("A", 0.0, 0.0, 0),
+            point("A", 1.5, 0.0, 1),
+            point("A", 4.0, 0.0, 2),
+        ];
+        let resampled = resample_path_distance(&history, 1.0, &database);
+        assert_eq!(xs(&resampled), vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn resample_edge_cases() {
+        let database = Database::new();
+        assert!(resample_path_distance(&[], 1.0, &database).is_empty());
+        assert_eq!(resample_path_distance(&line(1), 1.0, &database).len(), 1);
+        assert_eq!(resample_path_distance(&line(5), 0.0, &database).len(), 5);
+        // Duplicate points collapse on first one
+        let history = vec![point("A", 0.0, 0.0, 0), point("A", 0.0, 0.0, 1)];
+        assert_eq!(resample_path_distance(&history, 1.0, &database).len(), 1);
+    }
+
+    #[test]
+    fn monotonic_time_never_decrease() {
+        assert!(monotonic_time(&[]).is_empty());
+        let mut history = line(4);
+        history.reverse();
+        assert_eq!(cumulative_time(&history), vec![0.0, -1.0, -2.0, -3.0]);
+        assert_eq!(monotonic_time(&history), vec![0.0, 1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn resample_time() {
+        let database = Database::new();
+        let history = vec![point("A", 0.0, 0.0, 0), point("A", 10.0, 0.0, 10)];
+        let resampled = resample_path_time(&history, 5.0, &database);
+        assert_eq!(xs(&resampled), vec![0.0, 5.0, 10.0]);
+        assert_eq!(
+            resampled[1].space_time_position.timestamp,
+            Utc.timestamp_opt(1_700_000_005, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn interpolate_absolute_only_between_captures() {
+        let database = Database::new();
+        let a = point("A", 1.0, 0.0, 0);
+        let b = point("A", 3.0, 0.0, 10);
+        let middle = interpolate(&a, &b, 0.5, &database);
+        assert!(middle.is_capture());
+        assert_eq!(
+            middle.space_time_position.coordinates,
+            Vec3d::new(2.0, 0.0, 0.0)
+        );
+        assert_eq!(middle.local_coordinates, Vec3d::new(2.0, 0.0, 0.0));
+
+        // Map point, no absolute coordinates
+        let mut map_point = b.clone();
+        map_point.space_time_position.coordinates = Vec3d::default();
+        let middle = interpolate(&a, &map_point, 0.5, &database);
+        assert!(!middle.is_capture());
+        assert_eq!(middle.local_coordinates, Vec3d::new(2.0, 0.0, 0.0));
+    }
+}
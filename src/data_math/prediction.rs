@@ -0,0 +1,86 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use std::f64::consts::PI;
+
+/// Where a destination will be, seen from observer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prediction {
+    /// Seconds after observer time
+    pub time_offset: f64,
+    /// Absolute coordinates of destination at that time
+    pub coordinates: Vec3d,
+    /// Distance from observer in km
+    pub distance: f64,
+    /// Heading from observer sub point on destination container, in radian
+    pub bearing: f64,
+}
+
+/// Predict destination position after time offset (s)
+///
+/// Observer stays still, in space or on its own container.
+pub fn predict(
+    observer: &ProcessedPosition,
+    destination: Destination,
+    time_offset: f64,
+    database: &Database,
+) -> Option<Prediction> {
+    let time_elapsed = observer.time_elapsed + time_offset;
+    let observer_container = database.get(&observer.container_name)?;
+    let (container_name, local_coordinates) = destination.local();
+    let container = database.get(container_name)?;
+
+    let coordinates = local_coordinates.transform_to_absolute(time_elapsed, container);
+    let observer_absolute = observer
+        .local_coordinates
+        .transform_to_absolute(time_elapsed, observer_container);
+    let observer_local = observer_absolute.transform_to_local(time_elapsed, container);
+
+    Some(Prediction {
+        time_offset,
+        coordinates,
+        distance: (coordinates - observer_absolute).norm(),
+        bearing: (observer_local.loxodromie_to(local_coordinates) + 2.0 * PI) % (2.0 * PI),
+    })
+}
+
+/// Seconds until a local point rotate under a longitude fixed in space (radian)
+///
+/// Fixed longitude is measured in container frame without rotation. None if container does not rotate.
+pub fn time_until_longitude(
+    container: &Container,
+    local_coordinates: Vec3d,
+    fixed_longitude: f64,
+    time_elapsed: f64,
+) -> Option<f64> {
+    let speed = container.rotation_speed_in_degrees_per_second();
+    if speed == 0.0 {
+        return None;
+    }
+    // Fixed longitude move backward on the rotating surface
+    let current = fixed_longitude - container.rotation_state(time_elapsed).to_radians();
+    let angle = (current - local_coordinates.longitude()).rem_euclid(2.0 * PI);
+    Some(angle.to_degrees() / speed)
+}
+
+/// Longitude fixed in space facing the star, for container
+pub fn subsolar_longitude(container: &Container, database: &Database) -> Option<f64> {
+    let star = database.get("Stanton")?;
+    Some((star.coordinates - container.coordinates).longitude())
+}
@@ -0,0 +1,268 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Acceleration of a drive until a given speed
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct AccelerationStage {
+    /// Speed at end of stage in km/s
+    pub until_speed: f64,
+    /// Acceleration in km/s²
+    pub acceleration: f64,
+}
+
+/// Quantum drive performance, loaded from QtDrives.json
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct QtDrive {
+    pub name: String,
+    /// Seconds before the jump start
+    pub spool_time: f64,
+    /// Cruise speed in km/s
+    pub max_speed: f64,
+    /// Successive acceleration stages, deceleration mirror them
+    pub stages: Vec<AccelerationStage>,
+}
+
+impl Default for QtDrive {
+    fn default() -> Self {
+        Self {
+            name: "Default".to_string(),
+            spool_time: 5.0,
+            max_speed: 60_000.0,
+            stages: vec![
+                AccelerationStage {
+                    until_speed: 12_000.0,
+                    acceleration: 3_000.0,
+                },
+                AccelerationStage {
+                    until_speed: 60_000.0,
+                    acceleration: 1_500.0,
+                },
+            ],
+        }
+    }
+}
+
+impl QtDrive {
+    /// Seconds to jump over distance (km), spool included, None if drive can not make it
+    pub fn travel_time(&self, distance: f64) -> Option<f64> {
+        if !distance.is_finite() | (distance < 0.0) {
+            return None;
+        }
+        let half = distance / 2.0;
+        let mut speed = 0.0;
+        let mut covered = 0.0;
+        let mut time = 0.0;
+
+        // Accelerate until max speed or half way
+        for stage in &self.stages {
+            let target = stage.until_speed.min(self.max_speed);
+            if (target <= speed) | (stage.acceleration <= 0.0) {
+                continue;
+            }
+            let stage_distance = (target * target - speed * speed) / (2.0 * stage.acceleration);
+            if covered + stage_distance >= half {
+                let peak = (speed * speed + 2.0 * stage.acceleration * (half - covered)).sqrt();
+                time += (peak - speed) / stage.acceleration;
+                return Some(self.spool_time + 2.0 * time);
+            }
+            covered += stage_distance;
+            time += (target - speed) / stage.acceleration;
+            speed = target;
+        }
+        if speed <= 0.0 {
+            return None;
+        }
+        Some(self.spool_time + 2.0 * time + (distance - 2.0 * covered) / speed)
+    }
+}
+
+/// Drive with given name, or first one
+pub fn find_qt_drive<'a>(drives: &'a [QtDrive], name: &str) -> Option<&'a QtDrive> {
+    drives.iter().find(|d| d.name == name).or(drives.first())
+}
+
+/// A QT marker to jump to before flying to a target
+#[derive(Debug, Clone, PartialEq)]
+pub struct QtCandidate {
+    pub name: String,
+    /// Absolute coordinates at departure
+    pub coordinates: Vec3d,
+    /// Coordinates in container frame at arrival
+    pub arrival: Vec3d,
+    /// Jump duration in seconds
+    pub jump_time: f64,
+    /// Distance from marker to target at arrival, in km
+    pub remaining: f64,
+    /// Heading of final leg at arrival, in radian
+    pub bearing: f64,
+}
+
+/// Orbital markers stay fixed in space, they do not rotate with their container
+fn is_orbital_marker(poi: &Poi) -> bool {
+    poi.name.starts_with("OM-")
+}
+
+/// Rank QT markers of target container by distance left to fly at arrival, best first
+pub fn rank_qt_markers(
+    observer: &ProcessedPosition,
+    target: &ProcessedPosition,
+    database: &Database,
+    drive: &QtDrive,
+) -> Vec<QtCandidate> {
+    let (Some(observer_container), Some(container)) = (
+        database.get(&observer.container_name),
+        database.get(&target.container_name),
+    ) else {
+        return Vec::new();
+    };
+    let time_elapsed = observer.time_elapsed;
+    let origin = observer
+        .local_coordinates
+        .transform_to_absolute(time_elapsed, observer_container);
+
+    let mut candidates: Vec<QtCandidate> = container
+        .poi
+        .values()
+        .filter(|poi| poi.marker.unwrap_or(false))
+        .filter_map(|poi| {
+            let absolute = if is_orbital_marker(poi) {
+                poi.coordinates + container.coordinates
+            } else {
+                poi.coordinates
+                    .transform_to_absolute(time_elapsed, container)
+            };
+            let jump_time = drive.travel_time((absolute - origin).norm())?;
+
+            // Where marker is over the rotating surface when we arrive
+            let arrival = if is_orbital_marker(poi) {
+                absolute.transform_to_local(time_elapsed + jump_time, container)
+            } else {
+                poi.coordinates
+            };
+            Some(QtCandidate {
+                name: poi.name.clone(),
+                coordinates: absolute,
+                arrival,
+                jump_time,
+                remaining: (target.local_coordinates - arrival).norm(),
+                bearing: (arrival.loxodromie_to(target.local_coordinates) + 2.0 * PI) % (2.0 * PI),
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.remaining.total_cmp(&b.remaining));
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn travel_time_invalid_distances() {
+        let drive = QtDrive::default();
+        assert_eq!(drive.travel_time(f64::NAN), None);
+        assert_eq!(drive.travel_time(f64::INFINITY), None);
+        assert_eq!(drive.travel_time(-1.0), None);
+        assert_eq!(drive.travel_time(0.0), Some(drive.spool_time));
+    }
+
+    #[test]
+    fn travel_time_without_acceleration() {
+        let drive = QtDrive {
+            stages: Vec::new(),
+            ..Default::default()
+        };
+        assert_eq!(drive.travel_time(1000.0), None);
+        let drive = QtDrive {
+            stages: vec![AccelerationStage {
+                until_speed: 1000.0,
+                acceleration: 0.0,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(drive.travel_time(1000.0), None);
+    }
+
+    #[test]
+    fn travel_time_short_jump() {
+        // Never leave first stage: accelerate half way, decelerate the other half
+        let drive = QtDrive::default();
+        let acceleration = drive.stages[0].acceleration;
+        let distance = 1000.0;
+        let expected = drive.spool_time + 2.0 * (distance / acceleration).sqrt();
+        assert!(close(drive.travel_time(distance).unwrap(), expected));
+    }
+
+    #[test]
+    fn travel_time_long_jump() {
+        let drive = QtDrive::default();
+        // Distance and time of both stages, then cruise
+        let (mut distance, mut time, mut speed) = (0.0, 0.0, 0.0);
+        for stage in &drive.stages {
+            distance += (stage.until_speed.powi(2) - speed * speed) / (2.0 * stage.acceleration);
+            time += (stage.until_speed - speed) / stage.acceleration;
+            speed = stage.until_speed;
+        }
+        let cruise = 1e7;
+        let expected = drive.spool_time + 2.0 * time + cruise / drive.max_speed;
+        assert!(close(
+            drive.travel_time(2.0 * distance + cruise).unwrap(),
+            expected
+        ));
+    }
+
+    #[test]
+    fn travel_time_is_continuous_and_increasing() {
+        let drive = QtDrive::default();
+        let mut last = drive.travel_time(0.0).unwrap();
+        let mut distance = 1.0;
+        while distance < 1e9 {
+            let time = drive.travel_time(distance).unwrap();
+            assert!(time > last, "{distance}km in {time}s, less than {last}s");
+            // No jump at stage boundaries
+            assert!(time - last < 0.5 * (last - drive.spool_time) + 1.0);
+            last = time;
+            distance *= 1.1;
+        }
+    }
+
+    #[test]
+    fn find_drive_fall_back_to_first() {
+        let drives = vec![
+            QtDrive {
+                name: "A".to_string(),
+                ..Default::default()
+            },
+            QtDrive {
+                name: "B".to_string(),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(find_qt_drive(&drives, "B").unwrap().name, "B");
+        assert_eq!(find_qt_drive(&drives, "C").unwrap().name, "A");
+        assert!(find_qt_drive(&[], "A").is_none());
+    }
+}
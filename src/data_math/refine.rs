@@ -0,0 +1,214 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use std::collections::HashMap;
+
+/// Beyond this many scaled median deviations from the median, a capture is an outlier
+const OUTLIER_THRESHOLD: f64 = 3.0;
+/// Scale median absolute deviation to a standard deviation
+const MAD_SCALE: f64 = 1.4826;
+
+/// Location refined from several captures of the same place
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefinedLocation {
+    pub container_name: String,
+    /// Mean of kept captures in container frame
+    pub coordinates: Vec3d,
+    /// Number of captures kept
+    pub samples: usize,
+    /// Number of captures rejected as outliers or in another container
+    pub rejected: usize,
+    /// RMS distance of kept captures to mean, in km
+    pub spread: f64,
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+/// Average captures in their container frame, ignoring outliers
+///
+/// Only captures of the most represented container are used. Local coordinates do not depend on
+/// capture time, so captures taken at different rotation states can be averaged directly.
+pub fn refine_location(positions: &[&ProcessedPosition]) -> Option<RefinedLocation> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for position in positions {
+        *counts.entry(&position.container_name).or_default() += 1;
+    }
+    let (container_name, _) = counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))?;
+
+    // Space captures have no local frame, use absolute coordinates
+    let points: Vec<Vec3d> = positions
+        .iter()
+        .filter(|p| p.container_name == container_name)
+        .map(|p| {
+            if container_name == "Space" {
+                p.space_time_position.coordinates
+            } else {
+                p.local_coordinates
+            }
+        })
+        .collect();
+
+    // Component wise median is robust to outliers
+    let center = Vec3d::new(
+        median(&mut points.iter().map(|p| p.x).collect::<Vec<f64>>()),
+        median(&mut points.iter().map(|p| p.y).collect::<Vec<f64>>()),
+        median(&mut points.iter().map(|p| p.z).collect::<Vec<f64>>()),
+    );
+    let deviation = median(
+        &mut points
+            .iter()
+            .map(|p| (*p - center).norm())
+            .collect::<Vec<f64>>(),
+    );
+    // Duplicate captures give no deviation, keep anything within capture precision
+    let limit = (OUTLIER_THRESHOLD * MAD_SCALE * deviation)
+        .max(OUTLIER_THRESHOLD * DEFAULT_CAPTURE_PRECISION);
+    let kept: Vec<Vec3d> = points
+        .into_iter()
+        .filter(|p| (*p - center).norm() <= limit)
+        .collect();
+
+    let samples = kept.len();
+    let coordinates =
+        kept.iter().fold(Vec3d::default(), |sum, p| sum + *p) * (1.0 / samples as f64);
+    let spread = (kept
+        .iter()
+        .map(|p| {
+            let d = (*p - coordinates).norm();
+            d * d
+        })
+        .sum::<f64>()
+        / samples as f64)
+        .sqrt();
+
+    Some(RefinedLocation {
+        container_name: container_name.to_string(),
+        coordinates,
+        samples,
+        rejected: positions.len() - samples,
+        spread,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(container: &str, x: f64, y: f64, z: f64) -> ProcessedPosition {
+        ProcessedPosition {
+            space_time_position: SpaceTimePosition {
+                coordinates: Vec3d::new(x, y, z) + Vec3d::new(1e6, 0.0, 0.0),
+                ..Default::default()
+            },
+            local_coordinates: Vec3d::new(x, y, z),
+            container_name: container.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn refine(captures: &[ProcessedPosition]) -> Option<RefinedLocation> {
+        refine_location(&captures.iter().collect::<Vec<&ProcessedPosition>>())
+    }
+
+    #[test]
+    fn median_even_and_odd() {
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), 2.5);
+    }
+
+    #[test]
+    fn no_capture() {
+        assert_eq!(refine(&[]), None);
+    }
+
+    #[test]
+    fn single_capture() {
+        let result = refine(&[capture("A", 1.0, 2.0, 3.0)]).unwrap();
+        assert_eq!(result.coordinates, Vec3d::new(1.0, 2.0, 3.0));
+        assert_eq!((result.samples, result.rejected), (1, 0));
+        assert_eq!(result.spread, 0.0);
+    }
+
+    #[test]
+    fn duplicate_captures_have_no_deviation() {
+        // Median deviation is 0, close capture must not be rejected
+        let mut captures = vec![capture("A", 1.0, 2.0, 3.0); 4];
+        captures.push(capture("A", 1.0005, 2.0, 3.0));
+        let result = refine(&captures).unwrap();
+        assert_eq!((result.samples, result.rejected), (5, 0));
+        assert!((result.coordinates.x - 1.0001).abs() < 1e-9);
+        assert!(result.spread.is_finite());
+
+        // Far one still is
+        captures.push(capture("A", 5.0, 2.0, 3.0));
+        let result = refine(&captures).unwrap();
+        assert_eq!((result.samples, result.rejected), (5, 1));
+    }
+
+    #[test]
+    fn outlier_is_rejected() {
+        let captures = vec![
+            capture("A", 0.0, 0.0, 100.0),
+            capture("A", 0.01, 0.0, 100.0),
+            capture("A", 0.0, 0.01, 100.0),
+            capture("A", -0.01, 0.0, 100.0),
+            capture("A", 0.0, -0.01, 100.0),
+            capture("A", 1.0, 1.0, 100.0),
+        ];
+        let result = refine(&captures).unwrap();
+        assert_eq!((result.samples, result.rejected), (5, 1));
+        assert!(result.coordinates.x.abs() < 1e-12);
+        assert!(result.coordinates.y.abs() < 1e-12);
+        let spread = (4.0 * 0.01_f64.powi(2) / 5.0).sqrt();
+        assert!((result.spread - spread).abs() < 1e-12);
+    }
+
+    #[test]
+    fn most_represented_container_wins() {
+        let captures = vec![
+            capture("A", 0.0, 0.0, 100.0),
+            capture("B", 50.0, 0.0, 100.0),
+            capture("B", 50.0, 0.0, 100.0),
+        ];
+        let result = refine(&captures).unwrap();
+        assert_eq!(result.container_name, "B");
+        assert_eq!((result.samples, result.rejected), (2, 1));
+        assert_eq!(result.coordinates, Vec3d::new(50.0, 0.0, 100.0));
+    }
+
+    #[test]
+    fn space_uses_absolute_coordinates() {
+        let captures = vec![
+            capture("Space", 0.0, 0.0, 0.0),
+            capture("Space", 2.0, 0.0, 0.0),
+        ];
+        let result = refine(&captures).unwrap();
+        assert_eq!(result.coordinates, Vec3d::new(1e6 + 1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,347 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+
+use serde::{Deserialize, Serialize};
+
+/// A POI to visit, as stored in route files
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RouteStop {
+    pub container: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LegKind {
+    Quantum,
+    Surface,
+}
+
+/// One step of an itinerary
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leg {
+    pub kind: LegKind,
+    pub from: String,
+    pub to: String,
+    pub container_name: String,
+    /// Distance in km
+    pub distance: f64,
+    /// Heading in radian, NaN for quantum legs
+    pub bearing: f64,
+    /// Estimated duration in seconds, NaN if drive can not make the jump
+    pub duration: f64,
+    /// Body in the way of a quantum leg
+    pub obstruction: Option<String>,
+}
+
+/// Point of an itinerary, at the time we reach it
+fn waypoint(
+    name: &str,
+    container_name: &str,
+    local_coordinates: Vec3d,
+    time_elapsed: f64,
+) -> ProcessedPosition {
+    ProcessedPosition {
+        space_time_position: SpaceTimePosition::default(),
+        local_coordinates,
+        time_elapsed,
+        container_name: container_name.to_string(),
+        name: name.to_string(),
+        latitude: f64::NAN,
+        longitude: f64::NAN,
+        altitude: f64::NAN,
+        color: None,
+    }
+}
+
+/// Chain quantum legs to the best clear marker and surface legs to each stop
+///
+/// Surface legs are flown at cruise speed in m/s.
+pub fn plan_route(
+    start: &ProcessedPosition,
+    stops: &[Poi],
+    database: &Database,
+    drive: &QtDrive,
+    cruise_speed: f64,
+) -> Vec<Leg> {
+    let mut legs = Vec::new();
+    let mut position = start.clone();
+
+    for stop in stops {
+        let Some(container) = database.get(&stop.container) else {
+            continue;
+        };
+        let Some(position_container) = database.get(&position.container_name) else {
+            continue;
+        };
+        let target = waypoint(
+            &stop.name,
+            &stop.container,
+            stop.coordinates,
+            position.time_elapsed,
+        );
+
+        if (position.container_name != stop.container) | (stop.container == "Space") {
+            let origin = position
+                .local_coordinates
+                .transform_to_absolute(position.time_elapsed, position_container);
+            let candidates = rank_qt_markers(&position, &target, database, drive);
+            let chosen = candidates
+                .iter()
+                .find(|c| line_obstruction(origin, c.coordinates, database).is_none())
+                .or(candidates.first());
+
+            // No marker in container, jump straight to stop
+            let (name, coordinates, arrival, jump_time) = match chosen {
+                Some(c) => (c.name.clone(), c.coordinates, c.arrival, c.jump_time),
+                None => {
+                    let coordinates = stop
+                        .coordinates
+                        .transform_to_absolute(position.time_elapsed, container);
+                    let jump_time = drive
+                        .travel_time((coordinates - origin).norm())
+                        .unwrap_or(f64::NAN);
+                    (stop.name.clone(), coordinates, stop.coordinates, jump_time)
+                }
+            };
+            legs.push(Leg {
+                kind: LegKind::Quantum,
+                from: position.name.clone(),
+                to: name.clone(),
+                container_name: stop.container.clone(),
+                distance: (coordinates - origin).norm(),
+                bearing: f64::NAN,
+                duration: jump_time,
+                obstruction: line_obstruction(origin, coordinates, database),
+            });
+            position = waypoint(
+                &name,
+                &stop.container,
+                arrival,
+                // Unknown jump time, assume we arrive right away
+                position.time_elapsed + jump_time.max(0.0),
+            );
+        }
+
+        // Fly the rest, unless marker is the stop
+        let geometry =
+            relative_geometry(&position, Destination::Poi(stop), database).unwrap_or_default();
+        if geometry.distance > 0.001 {
+            let duration = geometry.distance * 1000.0 / cruise_speed;
+            legs.push(Leg {
+                kind: LegKind::Surface,
+                from: position.name.clone(),
+                to: stop.name.clone(),
+                container_name: stop.container.clone(),
+                distance: geometry.distance,
+                bearing: geometry.bearing,
+                duration,
+                obstruction: None,
+            });
+            position.time_elapsed += duration;
+        }
+        position = waypoint(
+            &stop.name,
+            &stop.container,
+            stop.coordinates,
+            position.time_elapsed,
+        );
+    }
+    legs
+}
+
+/// Great circle distance at sea level between two points of a container, in km
+pub fn great_circle_distance(a: Vec3d, b: Vec3d, radius: f64) -> f64 {
+    radius * a.angle_with(&b).to_radians()
+}
+
+/// Order to visit points from start, nearest neighbour improved by 2-opt
+///
+/// Return indices into points, start is not included.
+pub fn optimize_visit_order(
+    start: Vec3d,
+    points: &[Vec3d],
+    radius: f64,
+    return_to_start: bool,
+) -> Vec<usize> {
+    // Node 0 is start, node i + 1 is points[i]
+    let nodes: Vec<Vec3d> = std::iter::once(start)
+        .chain(points.iter().copied())
+        .collect();
+    let matrix: Vec<Vec<f64>> = nodes
+        .iter()
+        .map(|a| {
+            nodes
+                .iter()
+                .map(|b| great_circle_distance(*a, *b, radius))
+                .collect()
+        })
+        .collect();
+
+    // Nearest neighbour
+    let mut tour = vec![0];
+    let mut left: Vec<usize> = (1..nodes.len()).collect();
+    while !left.is_empty() {
+        let last = *tour.last().unwrap();
+        let (index, _) = left
+            .iter()
+            .enumerate()
+            .min_by(|a, b| matrix[last][*a.1].total_cmp(&matrix[last][*b.1]))
+            .unwrap();
+        tour.push(left.remove(index));
+    }
+
+    // 2-opt, start stay first
+    let next = |tour: &[usize], j: usize| match tour.get(j + 1) {
+        Some(node) => Some(*node),
+        None if return_to_start => Some(0),
+        None => None,
+    };
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 1..tour.len() {
+            for j in i + 1..tour.len() {
+                let (a, b, c) = (tour[i - 1], tour[i], tour[j]);
+                let delta = match next(&tour, j) {
+                    Some(d) => matrix[a][c] + matrix[b][d] - matrix[a][b] - matrix[c][d],
+                    None => matrix[a][c] - matrix[a][b],
+                };
+                if delta < -1e-9 {
+                    tour[i..=j].reverse();
+                    improved = true;
+                }
+            }
+        }
+    }
+    tour[1..].iter().map(|node| node - 1).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: f64 = 100.0;
+
+    /// Point at sea level on a container of RADIUS
+    fn at(latitude: f64, longitude: f64) -> Vec3d {
+        Geodetic {
+            latitude: latitude.to_radians(),
+            longitude: longitude.to_radians(),
+            altitude: 0.0,
+        }
+        .to_local(RADIUS)
+    }
+
+    fn tour_length(start: Vec3d, points: &[Vec3d], order: &[usize], closed: bool) -> f64 {
+        let mut stops = vec![start];
+        stops.extend(order.iter().map(|i| points[*i]));
+        if closed {
+            stops.push(start);
+        }
+        stops
+            .windows(2)
+            .map(|w| great_circle_distance(w[0], w[1], RADIUS))
+            .sum()
+    }
+
+    fn permutations(n: usize) -> Vec<Vec<usize>> {
+        if n == 0 {
+            return vec![Vec::new()];
+        }
+        let mut all = Vec::new();
+        for permutation in permutations(n - 1) {
+            for i in 0..=permutation.len() {
+                let mut p = permutation.clone();
+                p.insert(i, n - 1);
+                all.push(p);
+            }
+        }
+        all
+    }
+
+    fn is_permutation(order: &[usize], n: usize) -> bool {
+        let mut sorted = order.to_vec();
+        sorted.sort();
+        sorted == (0..n).collect::<Vec<usize>>()
+    }
+
+    #[test]
+    fn great_circle_on_equator() {
+        let distance = great_circle_distance(at(0.0, 0.0), at(0.0, 90.0), RADIUS);
+        assert!((distance - RADIUS * std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert_eq!(
+            great_circle_distance(at(10.0, 20.0), at(10.0, 20.0), RADIUS),
+            0.0
+        );
+    }
+
+    #[test]
+    fn visit_order_edge_cases() {
+        let start = at(0.0, 0.0);
+        assert!(optimize_visit_order(start, &[], RADIUS, false).is_empty());
+        assert!(optimize_visit_order(start, &[], RADIUS, true).is_empty());
+        assert_eq!(
+            optimize_visit_order(start, &[at(1.0, 1.0)], RADIUS, true),
+            vec![0]
+        );
+
+        // Duplicates and points on start are still all visited once
+        let points = [start, at(1.0, 0.0), at(1.0, 0.0), start];
+        for closed in [false, true] {
+            let order = optimize_visit_order(start, &points, RADIUS, closed);
+            assert!(is_permutation(&order, points.len()));
+            // No detour, go to the other place once and come back, acos is not exact at 0
+            let round_trip = 2.0 * great_circle_distance(start, points[1], RADIUS);
+            assert!(tour_length(start, &points, &order, closed) <= round_trip + 1e-4);
+        }
+    }
+
+    #[test]
+    fn two_opt_improves_nearest_neighbour() {
+        // Nearest neighbour goes east first, then has to cross back west and east again
+        let start = at(0.0, 0.0);
+        let points = [at(0.0, 1.0), at(0.0, -2.0), at(0.0, 5.0)];
+        let order = optimize_visit_order(start, &points, RADIUS, false);
+        assert_eq!(order, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn visit_order_is_optimal_on_small_sets() {
+        // Points around a ring, listed in scrambled order
+        let start = at(0.0, 0.0);
+        let points: Vec<Vec3d> = [3, 6, 1, 5, 2, 4]
+            .iter()
+            .map(|i| {
+                let angle = (*i as f64 * 60.0).to_radians();
+                at(5.0 * angle.sin(), 5.0 - 5.0 * angle.cos())
+            })
+            .collect();
+
+        for closed in [false, true] {
+            let order = optimize_visit_order(start, &points, RADIUS, closed);
+            assert!(is_permutation(&order, points.len()));
+            let best = permutations(points.len())
+                .iter()
+                .map(|p| tour_length(start, &points, p, closed))
+                .fold(f64::INFINITY, f64::min);
+            let length = tour_length(start, &points, &order, closed);
+            assert!(length <= best + 1e-9, "{length} > {best}");
+        }
+    }
+}
@@ -21,10 +21,28 @@ use egui::{
     color_picker::color_picker_color32, CollapsingHeader, Color32, ComboBox, Context, Grid,
     RichText, TextEdit, TopBottomPanel, Ui,
 };
-use egui_plot::{Line, Plot, Points};
+use egui_plot::{Line, LineStyle, MarkerShape, Plot, Points};
+use std::sync::Mutex;
 
 /// Dirty (but working way) too get snapped point on graph see [issue](https://github.com/emilk/egui/discussions/1778)
-static mut SMARTY: String = String::new();
+static SMARTY: Mutex<String> = Mutex::new(String::new());
+
+/// Plot coordinates of map, longitude and latitude in degrees
+fn map_point(geodetic: Geodetic) -> [f64; 2] {
+    [
+        geodetic.longitude.to_degrees(),
+        geodetic.latitude.to_degrees(),
+    ]
+}
+
+/// Angular radius in degrees of error circle around a capture, radius in km
+fn error_angle(point: &ProcessedPosition, radius: f64, database: &Database) -> Option<f64> {
+    let sea_level = database.get(&point.container_name)?.radius_body;
+    if (radius <= 0.0) | (sea_level <= 0.0) | !point.latitude.is_finite() {
+        return None;
+    }
+    Some((radius / sea_level).to_degrees())
+}
 
 impl MyEguiApp {
     pub fn display(&mut self, ctx: &Context) {
@@ -39,6 +57,26 @@ impl MyEguiApp {
 
         self.display_global_store(ctx);
 
+        self.display_calibration(ctx);
+
+        self.display_reprocess(ctx);
+
+        self.display_edit_history(ctx);
+
+        self.display_path_tools(ctx);
+
+        self.display_replay(ctx);
+
+        self.display_race(ctx);
+
+        self.display_route(ctx);
+
+        self.display_descent(ctx);
+
+        self.display_refine(ctx);
+
+        self.display_dedupe(ctx);
+
         // Display top row
         self.display_top(ctx);
 
@@ -67,12 +105,14 @@ impl MyEguiApp {
                             };
                             if ui.button("Add").clicked() {
                                 if let Some(path) = self.global_paths.get_mut(&self.path_selector) {
-                                    if path.history.is_empty() {
-                                        path.history.push(point.clone());
-                                    } else {
-                                        path.history.insert(path.current_index, point.clone());
-                                    }
+                                    let index = path.current_index.min(path.history.len());
+                                    path.history.insert(index, point.clone());
                                     path.current_index += 1;
+                                    self.edit_history.push(Edit::PathPointAdded {
+                                        name: path.name.clone(),
+                                        index,
+                                        point: point.clone(),
+                                    });
                                 }
                             };
                             ui.label(&point.name);
@@ -85,6 +125,1078 @@ impl MyEguiApp {
             });
     }
 
+    fn display_calibration(&mut self, ctx: &Context) {
+        let calibration = &mut self.calibration;
+        let mut widget_open = calibration.widget_open;
+        let mut settings_changed = false;
+        egui::Window::new("Calibration")
+            .open(&mut widget_open)
+            .show(ctx, |ui| {
+                Grid::new("CalibrationSelector").show(ui, |ui| {
+                    ui.label("Container");
+                    ComboBox::from_id_source("CalibrationContainer")
+                        .selected_text(&calibration.container)
+                        .show_ui(ui, |ui| {
+                            for container in self.database.values() {
+                                ui.selectable_value(
+                                    &mut calibration.container,
+                                    container.name.clone(),
+                                    container.name.clone(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Poi");
+                    ComboBox::from_id_source("CalibrationPoi")
+                        .selected_text(&calibration.poi)
+                        .show_ui(ui, |ui| {
+                            if let Some(container) = self.database.get(&calibration.container) {
+                                for poi in container.poi.values() {
+                                    ui.selectable_value(
+                                        &mut calibration.poi,
+                                        poi.name.clone(),
+                                        poi.name.clone(),
+                                    );
+                                }
+                            }
+                        });
+                    ui.end_row();
+                });
+
+                // Only real captures in the calibrated container are relevant
+                let last_capture = self
+                    .global_history
+                    .last()
+                    .filter(|p| (p.container_name == calibration.container) & p.is_capture());
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            last_capture.is_some(),
+                            egui::Button::new("Add last capture"),
+                        )
+                        .clicked()
+                    {
+                        calibration.samples.push(last_capture.unwrap().clone());
+                        calibration.result = None;
+                    };
+                    if ui.button("Clear").clicked() {
+                        calibration.samples = Vec::new();
+                        calibration.result = None;
+                    };
+                });
+
+                let mut eviction = None;
+                for (index, sample) in calibration.samples.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.spacing_mut().item_spacing = egui::vec2(1.0, 1.0);
+                        if ui.button("❌").clicked() {
+                            eviction = Some(index);
+                        };
+                        ui.label(format!(
+                            "{} - {}",
+                            sample.name,
+                            sample.space_time_position.timestamp.format("%H:%M:%S")
+                        ));
+                    });
+                }
+                if let Some(i) = eviction {
+                    calibration.samples.remove(i);
+                    calibration.result = None;
+                }
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut calibration.fit_rotation_speed, "Fit rotation speed");
+                    if ui.button("Solve").clicked() {
+                        calibration.solve(&self.database);
+                    };
+                });
+
+                if let (Some(result), Some(container)) = (
+                    calibration.result,
+                    self.database.get(&calibration.container),
+                ) {
+                    Grid::new("CalibrationResult").show(ui, |ui| {
+                        ui.label("");
+                        ui.label("Database");
+                        ui.label("Solved");
+                        ui.end_row();
+                        ui.label("Rotation adjust:");
+                        ui.label(format!("{:.6}°", container.rotation_adjust));
+                        ui.label(format!("{:.6}°", result.rotation_adjust));
+                        ui.end_row();
+                        ui.label("Rotation speed:");
+                        ui.label(format!("{:.8}h", container.rotation_speed));
+                        ui.label(format!("{:.8}h", result.rotation_speed));
+                        ui.end_row();
+                        ui.label("Residual:");
+                        ui.label(format!("{:.3}km", result.residual_before));
+                        ui.label(format!("{:.3}km", result.residual_after));
+                        ui.end_row();
+                    });
+                    if ui.button("Apply & save override").clicked() {
+                        calibration.apply(&mut self.database);
+                        self.reprocess_widget = true;
+                        settings_changed = true;
+                    };
+                }
+
+                ui.separator();
+                Grid::new("CalibrationClock").show(ui, |ui| {
+                    ui.label("Reference time:");
+                    ui.label(format!("{}", self.reference_time));
+                    ui.end_row();
+                    ui.label("Clock offset:");
+                    // Reprocess once value is set, not on every frame of a drag
+                    let response = ui.add(
+                        egui::DragValue::new(&mut self.settings.clock_offset)
+                            .speed(0.1)
+                            .suffix("s"),
+                    );
+                    settings_changed |= response.drag_released() | response.lost_focus();
+                    ui.end_row();
+                    ui.label("Capture latency:")
+                        .on_hover_text("Used to estimate capture uncertainty");
+                    // Only used for uncertainty, no need to reprocess
+                    let response = ui.add(
+                        egui::DragValue::new(&mut self.settings.capture_latency)
+                            .speed(0.01)
+                            .clamp_range(0.0..=10.0)
+                            .suffix("s"),
+                    );
+                    if response.drag_released() | response.lost_focus() {
+                        save_settings(&self.settings);
+                    }
+                    ui.end_row();
+                });
+
+                let clock_offset = self.database.get(&calibration.container).and_then(|c| {
+                    c.poi
+                        .get(&calibration.poi)
+                        .zip(last_capture)
+                        .and_then(|(poi, sample)| estimate_clock_offset(c, poi, sample))
+                });
+                if ui
+                    .add_enabled(
+                        clock_offset.is_some(),
+                        egui::Button::new("Sync clock on last capture"),
+                    )
+                    .clicked()
+                {
+                    self.settings.clock_offset += clock_offset.unwrap();
+                    settings_changed = true;
+                };
+            });
+        calibration.widget_open = widget_open;
+
+        if settings_changed {
+            save_settings(&self.settings);
+            self.reprocess_report = self.reprocess_positions();
+        }
+    }
+
+    fn display_reprocess(&mut self, ctx: &Context) {
+        let mut widget_open = self.reprocess_widget;
+        egui::Window::new("Reprocess")
+            .open(&mut widget_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button(format!("Path {}", self.path_selector)).clicked() {
+                        self.reprocess_report = self.reprocess_path(&self.path_selector.clone());
+                    };
+                    if ui.button("All paths").clicked() {
+                        self.reprocess_report = self.reprocess_paths();
+                    };
+                    if ui.button("Reload database").clicked() {
+                        self.custom_pois = CustomPoiStore::load();
+                        (self.database, self.reference_time) = load_database(&self.custom_pois);
+                        self.reprocess_report = self.reprocess_positions();
+                    };
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("File").clicked() {
+                        self.reprocess_report =
+                            self.reprocess_file(&self.reprocess_file_name.clone());
+                    };
+                    if ui
+                        .add(
+                            TextEdit::singleline(&mut self.reprocess_file_name)
+                                .hint_text("Path Name"),
+                        )
+                        .changed()
+                    {
+                        self.reprocess_file_pending = None;
+                    };
+                });
+                if self.reprocess_file_pending.is_some() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("Accept")
+                            .on_hover_text(format!(
+                                "Save as {}-reprocessed.json, original file is kept",
+                                self.reprocess_file_name
+                            ))
+                            .clicked()
+                        {
+                            self.save_reprocessed_file();
+                        };
+                        if ui.button("Discard").clicked() {
+                            self.reprocess_file_pending = None;
+                        };
+                    });
+                }
+                if self.custom_pois.has_legacy()
+                    && ui
+                        .button("Migrate CustomPoi.json")
+                        .on_hover_text("Move custom POIs into pois directory, one file each")
+                        .clicked()
+                {
+                    self.migration_report = self.custom_pois.migrate_legacy().to_string();
+                };
+                if !self.migration_report.is_empty() {
+                    ui.label(&self.migration_report);
+                }
+
+                ui.separator();
+                let moved: Vec<f64> = self
+                    .reprocess_report
+                    .iter()
+                    .map(|diff| diff.moved)
+                    .filter(|moved| !moved.is_nan())
+                    .collect();
+                let switched = self.reprocess_report.len() - moved.len();
+                Grid::new("ReprocessSummary").show(ui, |ui| {
+                    ui.label("Points:");
+                    ui.label(format!("{}", self.reprocess_report.len()));
+                    ui.end_row();
+                    ui.label("Max moved:");
+                    ui.label(format!(
+                        "{:.3}km",
+                        moved.iter().cloned().fold(0.0, f64::max)
+                    ));
+                    ui.end_row();
+                    ui.label("Mean moved:");
+                    ui.label(format!(
+                        "{:.3}km",
+                        moved.iter().sum::<f64>() / moved.len().max(1) as f64
+                    ));
+                    ui.end_row();
+                    ui.label("Container changed:");
+                    ui.label(format!("{switched}"));
+                    ui.end_row();
+                });
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("ReprocessReport").show(ui, |ui| {
+                        for diff in &self.reprocess_report {
+                            ui.label(&diff.name);
+                            if diff.container_before == diff.container_after {
+                                ui.label(&diff.container_after);
+                                ui.label(format!("{:.3}km", diff.moved));
+                            } else {
+                                ui.label(format!(
+                                    "{} → {}",
+                                    diff.container_before, diff.container_after
+                                ));
+                                ui.label("");
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        self.reprocess_widget = widget_open;
+    }
+
+    fn display_edit_history(&mut self, ctx: &Context) {
+        let mut widget_open = self.edit_history.widget_open;
+        let (mut undo, mut redo) = (false, false);
+        egui::Window::new("Edit History")
+            .open(&mut widget_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    undo = ui
+                        .add_enabled(
+                            !self.edit_history.undo.is_empty(),
+                            egui::Button::new("⟲ Undo"),
+                        )
+                        .on_hover_text("Ctrl+Z")
+                        .clicked();
+                    redo = ui
+                        .add_enabled(
+                            !self.edit_history.redo.is_empty(),
+                            egui::Button::new("⟳ Redo"),
+                        )
+                        .on_hover_text("Ctrl+Shift+Z")
+                        .clicked();
+                });
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    // Undone edits greyed on top, then done edits from last to first
+                    for edit in self.edit_history.redo.iter() {
+                        ui.weak(edit.description());
+                    }
+                    for edit in self.edit_history.undo.iter().rev() {
+                        ui.label(edit.description());
+                    }
+                });
+            });
+        self.edit_history.widget_open = widget_open;
+        if undo {
+            self.undo();
+        } else if redo {
+            self.redo();
+        }
+    }
+
+    fn display_path_tools(&mut self, ctx: &Context) {
+        let tools = &mut self.path_tools;
+        let path_names: Vec<String> = self.global_paths.keys().cloned().collect();
+        let Some(path) = self.global_paths.get_mut(&tools.path) else {
+            return;
+        };
+        let (mut split, mut reverse, mut duplicate, mut crop, mut merge) =
+            (false, false, false, false, false);
+        let mut widget_open = tools.widget_open;
+        egui::Window::new(format!("Path Tools - {}", path.name))
+            .open(&mut widget_open)
+            .show(ctx, |ui| {
+                ui.radio_value(&mut tools.operation, PathOperation::Simplify, "Simplify");
+                ui.radio_value(
+                    &mut tools.operation,
+                    PathOperation::ResampleDistance,
+                    "Resample by distance",
+                );
+                ui.radio_value(
+                    &mut tools.operation,
+                    PathOperation::ResampleTime,
+                    "Resample by time",
+                );
+                ui.radio_value(
+                    &mut tools.operation,
+                    PathOperation::RemoveStationary,
+                    "Remove stationary",
+                );
+                ui.horizontal(|ui| match tools.operation {
+                    PathOperation::Simplify => {
+                        ui.label("Tolerance:");
+                        ui.add(
+                            egui::DragValue::new(&mut tools.tolerance)
+                                .speed(0.001)
+                                .clamp_range(0.0..=f64::INFINITY)
+                                .suffix("km"),
+                        );
+                    }
+                    PathOperation::ResampleDistance => {
+                        ui.label("Step:");
+                        ui.add(
+                            egui::DragValue::new(&mut tools.distance_step)
+                                .speed(0.01)
+                                .clamp_range(0.001..=f64::INFINITY)
+                                .suffix("km"),
+                        );
+                    }
+                    PathOperation::ResampleTime => {
+                        ui.label("Step:");
+                        ui.add(
+                            egui::DragValue::new(&mut tools.time_step)
+                                .speed(1.0)
+                                .clamp_range(1.0..=f64::INFINITY)
+                                .suffix("s"),
+                        );
+                    }
+                    PathOperation::RemoveStationary => {
+                        ui.label("Threshold:");
+                        ui.add(
+                            egui::DragValue::new(&mut tools.stationary_threshold)
+                                .speed(0.001)
+                                .clamp_range(0.0..=f64::INFINITY)
+                                .suffix("km"),
+                        );
+                    }
+                });
+
+                Grid::new("PathToolsReport").show(ui, |ui| {
+                    ui.label("");
+                    ui.label("Before");
+                    ui.label("After");
+                    ui.end_row();
+                    ui.label("Points:");
+                    ui.label(format!("{}", path.history.len()));
+                    ui.label(format!("{}", tools.preview.len()));
+                    ui.end_row();
+                    ui.label("Lenght:");
+                    ui.label(format!("{:.3}km", path_length(&path.history)));
+                    ui.label(format!("{:.3}km", path_length(&tools.preview)));
+                    ui.end_row();
+                });
+
+                if ui.button("Apply").clicked() {
+                    let before = std::mem::replace(&mut path.history, tools.preview.clone());
+                    path.current_index = path.current_index.min(path.history.len());
+                    self.edit_history
+                        .push_path(&path.name, before, &path.history);
+                };
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            (path.current_index > 0) & (path.current_index < path.history.len()),
+                            egui::Button::new("Split"),
+                        )
+                        .on_hover_text("Split after highlighted point")
+                        .clicked()
+                    {
+                        split = true;
+                    };
+                    reverse = ui.button("Reverse").clicked();
+                    duplicate = ui.button("Duplicate").clicked();
+                });
+                ui.horizontal(|ui| {
+                    let len = path.history.len().max(1);
+                    crop = ui.button("Crop").clicked();
+                    ui.add(egui::DragValue::new(&mut tools.crop_start).clamp_range(1..=len));
+                    ui.label("to");
+                    ui.add(egui::DragValue::new(&mut tools.crop_end).clamp_range(1..=len));
+                });
+                ui.horizontal(|ui| {
+                    merge = ui.button("Merge").clicked();
+                    ComboBox::from_id_source("PathToolsMerge")
+                        .selected_text(&tools.merge_with)
+                        .show_ui(ui, |ui| {
+                            for name in &path_names {
+                                ui.selectable_value(&mut tools.merge_with, name.clone(), name);
+                            }
+                        });
+                    ui.checkbox(&mut tools.merge_by_time, "By time");
+                });
+            });
+        tools.widget_open = widget_open;
+
+        // Operations create new paths, original is untouched
+        let paths = &self.global_paths;
+        let path = &paths[&tools.path];
+        let mut new_paths = Vec::new();
+        if split {
+            if let Some((first, second)) = path.split(paths) {
+                new_paths.push(first);
+                new_paths.push(second);
+            }
+        }
+        if reverse {
+            new_paths.push(path.reversed(paths));
+        }
+        if duplicate {
+            new_paths.push(path.duplicate(paths));
+        }
+        if crop {
+            new_paths.push(path.cropped(paths, tools.crop_start, tools.crop_end));
+        }
+        if merge {
+            if let Some(other) = paths.get(&tools.merge_with) {
+                new_paths.push(path.merged(paths, other, tools.merge_by_time));
+            }
+        }
+        for path in new_paths {
+            self.global_paths.insert(path.name.clone(), path.clone());
+            self.edit_history.push(Edit::PathAdded(path));
+        }
+    }
+
+    fn display_replay(&mut self, ctx: &Context) {
+        let replay = &mut self.replay;
+        let mut widget_open = replay.widget_open;
+        egui::Window::new(format!("Replay - {}", replay.path))
+            .open(&mut widget_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing = egui::vec2(1.0, 1.0);
+                    if ui.button("⏮").clicked() {
+                        replay.time = 0.0;
+                    };
+                    if ui.button(if replay.playing { "⏸" } else { "⏵" }).clicked() {
+                        if !replay.playing & (replay.time >= replay.duration) {
+                            replay.time = 0.0;
+                        }
+                        replay.playing = !replay.playing;
+                    };
+                    ui.add(
+                        egui::DragValue::new(&mut replay.speed_multiplier)
+                            .speed(0.1)
+                            .clamp_range(0.1..=100.0)
+                            .prefix("x"),
+                    );
+                });
+                ui.add(
+                    egui::Slider::new(&mut replay.time, 0.0..=replay.duration.max(0.0))
+                        .show_value(false),
+                );
+                Grid::new("Replay").show(ui, |ui| {
+                    ui.label("Time:");
+                    ui.label(format!(
+                        "{}/{}",
+                        pretty_duration(chrono::Duration::seconds(replay.time as i64)),
+                        pretty_duration(chrono::Duration::seconds(replay.duration as i64))
+                    ));
+                    ui.end_row();
+                    ui.label("Heading:");
+                    ui.label(pretty(replay.heading));
+                    ui.end_row();
+                    ui.label("CIG Heading:");
+                    ui.label(borked_cig_heading(replay.heading));
+                    ui.end_row();
+                    ui.label("Speed:");
+                    ui.label(format!("{:.1}m/s", replay.speed));
+                    ui.end_row();
+                    ui.label("Ghost distance:");
+                    ui.label(format!("{:.3}km", replay.ghost_distance));
+                    ui.end_row();
+                });
+            });
+        replay.widget_open = widget_open;
+    }
+
+    fn display_race(&mut self, ctx: &Context) {
+        let race = &mut self.race;
+        let mut widget_open = race.widget_open;
+        egui::Window::new("Race")
+            .open(&mut widget_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Reference");
+                    ComboBox::from_id_source("RaceReference")
+                        .selected_text(&race.reference)
+                        .show_ui(ui, |ui| {
+                            for name in self.global_paths.keys() {
+                                ui.selectable_value(&mut race.reference, name.clone(), name);
+                            }
+                        });
+                    if ui
+                        .button(if race.active { "Restart" } else { "Start" })
+                        .on_hover_text("Race start on next capture")
+                        .clicked()
+                    {
+                        race.arm();
+                    };
+                });
+
+                let total = self
+                    .global_paths
+                    .get(&race.reference)
+                    .map(|p| path_length(&p.history))
+                    .unwrap_or(f64::NAN);
+                if let Some(last) = race.deltas.last() {
+                    let color = if last.delta <= 0.0 {
+                        Color32::GREEN
+                    } else {
+                        Color32::RED
+                    };
+                    ui.heading(RichText::new(format!("{:+.1}s", last.delta)).color(color));
+                    Grid::new("RaceLive").show(ui, |ui| {
+                        ui.label("Progress:");
+                        ui.label(format!("{:.3}/{:.3}km", last.distance, total));
+                        ui.end_row();
+                        ui.label("Cross track:");
+                        ui.label(format!("{:.3}km", last.cross_track));
+                        ui.end_row();
+                        ui.label("Time:");
+                        ui.label(pretty_duration(chrono::Duration::seconds(last.time as i64)));
+                        ui.end_row();
+                    });
+                } else if race.active {
+                    ui.label("Waiting for capture...");
+                }
+
+                CollapsingHeader::new("Segments")
+                    .id_source("RaceSegments")
+                    .show(ui, |ui| {
+                        egui::ScrollArea::vertical().show(ui, |ui| {
+                            Grid::new("RaceSegments").show(ui, |ui| {
+                                for (i, delta) in race.segment_deltas().iter().enumerate() {
+                                    ui.label(format!("{} → {}", i + 1, i + 2));
+                                    ui.label(format!("{delta:+.1}s"));
+                                    ui.end_row();
+                                }
+                            });
+                        });
+                    });
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!race.deltas.is_empty(), egui::Button::new("Export"))
+                        .clicked()
+                    {
+                        save_race_report(&race.export_name, &race.report());
+                    };
+                    ui.add(TextEdit::singleline(&mut race.export_name).hint_text("Race Name"));
+                });
+            });
+        race.widget_open = widget_open;
+    }
+
+    fn display_route(&mut self, ctx: &Context) {
+        let route = &mut self.route;
+        let live_point = self
+            .global_history_index
+            .checked_sub(1)
+            .and_then(|i| self.global_history.get(i));
+        let mut widget_open = route.widget_open;
+        let mut load_targets = false;
+        let mut optimize_targets = false;
+        egui::Window::new("Route")
+            .open(&mut widget_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ComboBox::from_id_source("RouteContainer")
+                        .selected_text(&route.selector_container)
+                        .show_ui(ui, |ui| {
+                            for name in self.database.keys() {
+                                ui.selectable_value(
+                                    &mut route.selector_container,
+                                    name.clone(),
+                                    name,
+                                );
+                            }
+                        });
+                    ComboBox::from_id_source("RoutePoi")
+                        .selected_text(&route.selector_poi)
+                        .show_ui(ui, |ui| {
+                            if let Some(container) = self.database.get(&route.selector_container) {
+                                for name in container.poi.keys() {
+                                    ui.selectable_value(
+                                        &mut route.selector_poi,
+                                        name.clone(),
+                                        name,
+                                    );
+                                }
+                            }
+                        });
+                    if ui.button("Add stop").clicked()
+                        & self
+                            .database
+                            .get(&route.selector_container)
+                            .is_some_and(|c| c.poi.contains_key(&route.selector_poi))
+                    {
+                        route.stops.push(RouteStop {
+                            container: route.selector_container.clone(),
+                            name: route.selector_poi.clone(),
+                        });
+                    };
+                });
+
+                // Stops, with reorder and removal
+                let mut swap = None;
+                let mut remove = None;
+                Grid::new("RouteStops").show(ui, |ui| {
+                    for (i, stop) in route.stops.iter().enumerate() {
+                        ui.label(format!("{}.", i + 1));
+                        ui.label(format!("{} - {}", stop.container, stop.name));
+                        ui.horizontal(|ui| {
+                            ui.spacing_mut().item_spacing = egui::vec2(1.0, 1.0);
+                            if ui.button("⏶").clicked() & (i > 0) {
+                                swap = Some(i - 1);
+                            };
+                            if ui.button("⏷").clicked() & (i + 1 < route.stops.len()) {
+                                swap = Some(i);
+                            };
+                            if ui.button("❌").clicked() {
+                                remove = Some(i);
+                            };
+                        });
+                        ui.end_row();
+                    }
+                });
+                if let Some(i) = swap {
+                    route.stops.swap(i, i + 1);
+                }
+                if let Some(i) = remove {
+                    route.stops.remove(i);
+                }
+
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut route.cruise_speed)
+                            .speed(1.0)
+                            .clamp_range(1.0..=1500.0)
+                            .prefix("Cruise ")
+                            .suffix("m/s"),
+                    );
+                    if ui
+                        .add_enabled(live_point.is_some(), egui::Button::new("Plan"))
+                        .on_disabled_hover_text("Need a position to start from")
+                        .clicked()
+                    {
+                        let drive =
+                            find_qt_drive(&self.qt_drives, &self.settings.qt_drive).unwrap();
+                        route.plan(live_point.unwrap(), &self.database, drive);
+                    };
+                    if ui
+                        .add_enabled(
+                            !route.stops.is_empty(),
+                            egui::Button::new("Load as targets"),
+                        )
+                        .clicked()
+                    {
+                        load_targets = true;
+                    };
+                });
+
+                ui.separator();
+                Grid::new("RouteLegs").striped(true).show(ui, |ui| {
+                    ui.label("Leg");
+                    ui.label("To");
+                    ui.label("Distance");
+                    ui.label("Heading");
+                    ui.label("Time");
+                    ui.end_row();
+                    for leg in &route.legs {
+                        ui.label(match leg.kind {
+                            LegKind::Quantum => "QT",
+                            LegKind::Surface => "Fly",
+                        });
+                        match &leg.obstruction {
+                            Some(body) => ui
+                                .label(RichText::new(&leg.to).color(Color32::RED))
+                                .on_hover_text(format!("Blocked by {body}")),
+                            None => ui.label(&leg.to),
+                        };
+                        ui.label(format!("{:.3}km", leg.distance));
+                        ui.label(pretty(leg.bearing));
+                        ui.label(pretty_seconds(leg.duration));
+                        ui.end_row();
+                    }
+                    if !route.legs.is_empty() {
+                        ui.label("Total");
+                        ui.label("");
+                        ui.label(format!("{:.3}km", route.total_distance()));
+                        ui.label("");
+                        ui.label(pretty_seconds(route.total_duration()));
+                        ui.end_row();
+                    }
+                });
+
+                ui.separator();
+                CollapsingHeader::new("Visit order")
+                    .id_source("RouteVisitOrder")
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut route.visit_return, "Return to start");
+                            if ui
+                                .add_enabled(
+                                    live_point.is_some(),
+                                    egui::Button::new("Optimize targets"),
+                                )
+                                .on_hover_text("Reorder targets on current container")
+                                .clicked()
+                            {
+                                optimize_targets = true;
+                            };
+                        });
+                        if !route.visit_route.is_empty() {
+                            ui.label(format!(
+                                "{}: {} targets, {:.3}km",
+                                route.visit_container,
+                                route.visit_route.len() - 1 - route.visit_return as usize,
+                                route.visit_distance
+                            ));
+                        }
+                        if !route.visit_message.is_empty() {
+                            ui.label(&route.visit_message);
+                        }
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        save_route(&route.file_name, &route.stops);
+                    };
+                    if ui.button("Load").clicked() {
+                        route.stops = load_route(&route.file_name);
+                        route.legs = Vec::new();
+                    };
+                    ui.add(TextEdit::singleline(&mut route.file_name).hint_text("Route Name"));
+                });
+            });
+        route.widget_open = widget_open;
+
+        if optimize_targets {
+            self.optimize_targets();
+        }
+
+        // Stops become targets, in route order
+        if load_targets {
+            for poi in self.route.stop_pois(&self.database) {
+                let target = Target::new(&poi, &self.database);
+                self.global_targets.push(target.clone());
+                self.edit_history
+                    .push(Edit::TargetAdded(self.global_targets.len() - 1, target));
+            }
+        }
+    }
+
+    fn display_descent(&mut self, ctx: &Context) {
+        let descent = &mut self.descent;
+        let mut widget_open = descent.widget_open;
+        egui::Window::new("Descent")
+            .open(&mut widget_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Target");
+                    ComboBox::from_id_source("DescentTarget")
+                        .selected_text(&descent.target)
+                        .show_ui(ui, |ui| {
+                            for target in &self.global_targets {
+                                let name = &target.current_point.name;
+                                if ui
+                                    .selectable_value(&mut descent.target, name.clone(), name)
+                                    .clicked()
+                                {
+                                    descent.restart();
+                                };
+                            }
+                        });
+                    if ui
+                        .button("Restart")
+                        .on_hover_text("Glide path start on next capture")
+                        .clicked()
+                    {
+                        descent.restart();
+                    };
+                });
+
+                let Some(guidance) = descent.guidance else {
+                    ui.label("Waiting for capture...");
+                    return;
+                };
+                let deviation =
+                    guidance.altitude - descent.planned_altitude(guidance.horizontal_distance);
+                Grid::new("Descent").show(ui, |ui| {
+                    ui.label("Heading:");
+                    ui.label(pretty(guidance.bearing));
+                    ui.end_row();
+                    ui.label("CIG Heading:");
+                    ui.label(borked_cig_heading(guidance.bearing));
+                    ui.end_row();
+                    ui.label("Descent angle:");
+                    ui.label(pretty(guidance.descent_angle));
+                    ui.end_row();
+                    ui.label("Horizontal distance:");
+                    ui.label(format!("{:.3}km", guidance.horizontal_distance));
+                    ui.end_row();
+                    ui.label("Altitude to lose:");
+                    ui.label(format!("{:.3}km", guidance.altitude_to_lose));
+                    ui.end_row();
+                    ui.label("Above glide path:");
+                    ui.label(format!("{deviation:+.3}km"));
+                    ui.end_row();
+                });
+
+                // Vertical profile, target on the left
+                Plot::new("DescentProfile")
+                    .height(160.0)
+                    .allow_scroll(false)
+                    .show(ui, |plot_ui| {
+                        if let Some(start) = descent.start {
+                            plot_ui.line(
+                                Line::new(vec![[0.0, descent.end_altitude], start])
+                                    .name("Glide path")
+                                    .color(Color32::GRAY)
+                                    .style(LineStyle::dashed_loose()),
+                            );
+                        }
+                        plot_ui.line(Line::new(descent.track.clone()).name("Track"));
+                        plot_ui.points(
+                            Points::new([guidance.horizontal_distance, guidance.altitude])
+                                .name("Current")
+                                .radius(4.0)
+                                .color(Color32::GOLD),
+                        );
+                    });
+            });
+        descent.widget_open = widget_open;
+    }
+
+    fn display_refine(&mut self, ctx: &Context) {
+        let refine = &mut self.refine;
+        let mut widget_open = refine.widget_open;
+        let mut changed = false;
+        let mut save = false;
+        egui::Window::new("Refine location")
+            .open(&mut widget_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Captures from");
+                    let mut source = refine.source.clone();
+                    ComboBox::from_id_source("RefineSource")
+                        .selected_text(if source.is_empty() {
+                            "Global history"
+                        } else {
+                            &source
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut source, String::new(), "Global history");
+                            for name in self.global_paths.keys() {
+                                ui.selectable_value(&mut source, name.clone(), name);
+                            }
+                        });
+                    if source != refine.source {
+                        refine.set_source(source);
+                    }
+                });
+
+                let captures = refine.captures(&self.global_history, &self.global_paths);
+                ui.horizontal(|ui| {
+                    if ui.button("All").clicked() {
+                        refine.selected = captures.iter().map(capture_key).collect();
+                        changed = true;
+                    };
+                    if ui.button("None").clicked() {
+                        refine.selected.clear();
+                        changed = true;
+                    };
+                });
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for capture in captures {
+                            let key = capture_key(capture);
+                            let mut checked = refine.selected.contains(&key);
+                            if ui
+                                .checkbox(
+                                    &mut checked,
+                                    format!("{} ({})", capture.name, capture.container_name),
+                                )
+                                .changed()
+                            {
+                                if checked {
+                                    refine.selected.insert(key);
+                                } else {
+                                    refine.selected.remove(&key);
+                                }
+                                changed = true;
+                            };
+                        }
+                    });
+
+                ui.separator();
+                let Some(result) = &refine.result else {
+                    ui.label("Select captures of the same place");
+                    return;
+                };
+                Grid::new("RefineResult").show(ui, |ui| {
+                    ui.label("Container:");
+                    ui.label(&result.container_name);
+                    ui.end_row();
+                    ui.label("Samples:");
+                    ui.label(format!("{} ({} rejected)", result.samples, result.rejected));
+                    ui.end_row();
+                    ui.label("Spread:");
+                    ui.label(format!("{:.3}km", result.spread));
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    ui.add(TextEdit::singleline(&mut refine.name).hint_text("POI Name"));
+                    if ui
+                        .add_enabled(!refine.name.is_empty(), egui::Button::new("Save POI"))
+                        .clicked()
+                    {
+                        save = true;
+                    };
+                });
+            });
+        refine.widget_open = widget_open;
+
+        if changed {
+            self.refine.update(&self.global_history, &self.global_paths);
+        }
+        if save {
+            self.save_refined_poi();
+        }
+    }
+
+    fn display_dedupe(&mut self, ctx: &Context) {
+        let dedupe = &mut self.dedupe;
+        let mut widget_open = dedupe.widget_open;
+        let mut merge = false;
+        egui::Window::new("Deduplicate POIs")
+            .open(&mut widget_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut dedupe.threshold)
+                            .speed(0.01)
+                            .clamp_range(0.001..=50.0)
+                            .prefix("Within ")
+                            .suffix("km"),
+                    );
+                    if ui.button("Search").clicked() {
+                        dedupe.search(&self.database, &self.custom_pois);
+                    };
+                });
+                if !dedupe.summary.is_empty() {
+                    ui.label(&dedupe.summary);
+                }
+
+                ui.separator();
+                if dedupe.proposals.is_empty() {
+                    ui.label("No duplicate");
+                    return;
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for (index, proposal) in dedupe.proposals.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut dedupe.accepted[index], "");
+                                ComboBox::from_id_source(format!("DedupeCanonical{index}"))
+                                    .selected_text(&proposal.canonical)
+                                    .show_ui(ui, |ui| {
+                                        for poi in &proposal.members {
+                                            ui.selectable_value(
+                                                &mut proposal.canonical,
+                                                poi.name.clone(),
+                                                &poi.name,
+                                            );
+                                        }
+                                    });
+                                ui.label(format!(
+                                    "{} ({:.3}km)",
+                                    proposal.container, proposal.spread
+                                ));
+                            });
+                            for poi in &proposal.members {
+                                let origin = if proposal.custom.contains(&poi_key(poi)) {
+                                    "custom"
+                                } else {
+                                    "imported"
+                                };
+                                ui.label(format!(
+                                    "    {} [{origin}] {:.3}km",
+                                    poi.name,
+                                    (poi.coordinates - proposal.coordinates).norm()
+                                ));
+                            }
+                        }
+                    });
+
+                ui.separator();
+                if ui
+                    .add_enabled(
+                        dedupe.accepted.iter().any(|a| *a),
+                        egui::Button::new("Merge selected"),
+                    )
+                    .clicked()
+                {
+                    merge = true;
+                };
+            });
+        dedupe.widget_open = widget_open;
+
+        if merge {
+            self.merge_pois();
+        }
+    }
+
     fn display_side(&mut self, ctx: &Context) {
         egui::SidePanel::left("my_left_panel").show(ctx, |ui| {
             egui::ScrollArea::vertical().show(ui, |ui| {
@@ -110,7 +1222,8 @@ impl MyEguiApp {
                             });
                         }
                         if let Some(i) = eviction {
-                            self.global_targets.remove(i);
+                            let target = self.global_targets.remove(i);
+                            self.edit_history.push(Edit::TargetRemoved(i, target));
                         }
                         if let Some(point) = focused {
                             self.add_to_global(&point);
@@ -146,6 +1259,13 @@ impl MyEguiApp {
                         if ui.button("🗺").clicked() {
                             path.widget_open = true;
                         };
+                        if ui.button("⏯").clicked() {
+                            self.replay.start(&path.name);
+                        };
+                        if ui.button("✂").clicked() {
+                            self.path_tools.widget_open = true;
+                            self.path_tools.path = path.name.clone();
+                        };
                         if ui.button("👁").clicked() {
                             path.map_displayed = !path.map_displayed;
                         };
@@ -202,14 +1322,25 @@ impl MyEguiApp {
                         }
                     });
 
-                    if let Some(i) = eviction_point {
-                        path.history.remove(i);
-                    } else if let Some(i) = up {
-                        let point = path.history.remove(i);
-                        path.history.insert(i.max(1) - 1, point)
-                    } else if let Some(i) = down {
-                        let point = path.history.remove(i);
-                        path.history.insert(i.min(len - 2) + 1, point)
+                    if let Some(index) = eviction_point {
+                        let point = path.history.remove(index);
+                        self.edit_history.push(Edit::PathPointRemoved {
+                            name: path.name.clone(),
+                            index,
+                            point,
+                        });
+                    } else if let Some((from, to)) = up
+                        .map(|i| (i, i.max(1) - 1))
+                        .or(down.map(|i| (i, i.min(len - 2) + 1)))
+                    {
+                        let point = path.history.remove(from);
+                        self.edit_history.push(Edit::PathPointMoved {
+                            name: path.name.clone(),
+                            point: point.name.clone(),
+                            from,
+                            to,
+                        });
+                        path.history.insert(to, point);
                     }
 
                     if eviction {
@@ -217,15 +1348,21 @@ impl MyEguiApp {
                         if path.history.is_empty() & (&path.name != "Self") {
                             // Is already empty remove path from global_paths
                             confirm_eviction.push(path.name.to_string());
-                        } else {
+                        } else if !path.history.is_empty() {
                             // Empty all point
-                            path.history = Vec::new();
+                            path.current_index = 0;
+                            self.edit_history.push(Edit::PathCleared {
+                                name: path.name.clone(),
+                                points: std::mem::take(&mut path.history),
+                            });
                         }
                     }
                 }
 
                 for path_name in confirm_eviction {
-                    self.global_paths.remove(&path_name);
+                    if let Some(path) = self.global_paths.remove(&path_name) {
+                        self.edit_history.push(Edit::PathRemoved(path));
+                    }
                 }
                 if let Some(point) = focused {
                     self.add_to_global(&point);
@@ -301,6 +1438,7 @@ impl MyEguiApp {
     fn display_top_middle(&mut self, ui: &mut Ui) {
         ui.vertical(|ui| {
             let mut eviction = false;
+            let mut save_poi = None;
 
             ui.horizontal(|ui| {
                 ui.heading("Path History");
@@ -350,13 +1488,7 @@ impl MyEguiApp {
                         ui.add(TextEdit::singleline(&mut position.name).hint_text("No_name"));
 
                         if ui.button("Save as POI").clicked() {
-                            let new_poi = save_to_poi(position);
-                            // Add to database
-                            self.database
-                                .get_mut(&new_poi.container)
-                                .unwrap()
-                                .poi
-                                .insert(new_poi.name.clone(), new_poi);
+                            save_poi = Some(position.clone());
                         };
                         ui.end_row();
                     });
@@ -366,9 +1498,18 @@ impl MyEguiApp {
             };
             if eviction {
                 if let Some(current_path) = self.global_paths.get_mut(&self.path_selector) {
-                    current_path.history.remove(current_path.current_index - 1);
+                    let index = current_path.current_index - 1;
+                    let point = current_path.history.remove(index);
+                    self.edit_history.push(Edit::PathPointRemoved {
+                        name: current_path.name.clone(),
+                        index,
+                        point,
+                    });
                 }
             }
+            if let Some(position) = save_poi {
+                self.save_position_to_poi(&position);
+            }
 
             // ui.separator(); // BUG fill entire right panel
             ui.label("---------------------------");
@@ -387,8 +1528,13 @@ impl MyEguiApp {
                     let mut path = Path::new(self.path_name_io.to_string());
                     path.map_color = random_color32();
                     path.history = import_history(&self.path_name_io);
-                    self.global_paths
-                        .insert(self.path_name_io.to_string(), path);
+                    if let Some(previous) = self
+                        .global_paths
+                        .insert(self.path_name_io.to_string(), path.clone())
+                    {
+                        self.edit_history.push(Edit::PathRemoved(previous));
+                    }
+                    self.edit_history.push(Edit::PathAdded(path));
                 };
                 ui.add(TextEdit::singleline(&mut self.path_name_io).hint_text("Path Name"));
             });
@@ -445,7 +1591,10 @@ impl MyEguiApp {
                         .poi
                         .get(&self.target_selector_poi)
                     {
-                        self.global_targets.push(Target::new(poi, &self.database));
+                        let target = Target::new(poi, &self.database);
+                        self.global_targets.push(target.clone());
+                        self.edit_history
+                            .push(Edit::TargetAdded(self.global_targets.len() - 1, target));
                         // TODO check for duplicate !
                     }
                 };
@@ -454,16 +1603,121 @@ impl MyEguiApp {
             });
             ui.label("------------------------");
             ui.checkbox(&mut self.path_add_point, "Auto add point");
+            ui.horizontal(|ui| {
+                ui.label("QT drive");
+                ComboBox::from_id_source("QtDrive")
+                    .selected_text(
+                        find_qt_drive(&self.qt_drives, &self.settings.qt_drive)
+                            .map(|d| d.name.clone())
+                            .unwrap_or_default(),
+                    )
+                    .show_ui(ui, |ui| {
+                        for drive in &self.qt_drives {
+                            if ui
+                                .selectable_value(
+                                    &mut self.settings.qt_drive,
+                                    drive.name.clone(),
+                                    &drive.name,
+                                )
+                                .changed()
+                            {
+                                save_settings(&self.settings);
+                            };
+                        }
+                    });
+            });
 
             if ui.button("GlobalStore").clicked() {
                 self.global_history_widget = !self.global_history_widget;
             }
+            if ui.button("Calibration").clicked() {
+                self.calibration.widget_open = !self.calibration.widget_open;
+            }
+            if ui.button("Reprocess").clicked() {
+                self.reprocess_widget = !self.reprocess_widget;
+            }
+            if ui.button("Race").clicked() {
+                self.race.widget_open = !self.race.widget_open;
+            }
+            if ui.button("Route").clicked() {
+                self.route.widget_open = !self.route.widget_open;
+            }
+            if ui.button("Descent").clicked() {
+                self.descent.widget_open = !self.descent.widget_open;
+            }
+            if ui.button("Refine").clicked() {
+                self.refine.widget_open = !self.refine.widget_open;
+            }
+            if ui.button("Dedupe").clicked() {
+                self.dedupe.widget_open = !self.dedupe.widget_open;
+            }
+            if ui.button("Edit History").clicked() {
+                self.edit_history.widget_open = !self.edit_history.widget_open;
+            }
         });
     }
 
     fn display_map(&mut self, ctx: &Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading("Map");
+            ui.horizontal(|ui| {
+                ui.heading("Map");
+                ComboBox::from_id_source("MapContainer")
+                    .selected_text(&self.map_container)
+                    .show_ui(ui, |ui| {
+                        for container in self.database.values() {
+                            if ui
+                                .selectable_value(
+                                    &mut self.map_container,
+                                    container.name.clone(),
+                                    container.name.clone(),
+                                )
+                                .clicked()
+                            {
+                                self.map_container_follow = false;
+                            };
+                        }
+                    });
+                ui.checkbox(&mut self.map_container_follow, "Follow");
+            });
+
+            // Legend of everything not on displayed container
+            let mut off_container = Vec::new();
+            for target in &self.global_targets {
+                if target.current_point.container_name != self.map_container {
+                    off_container.push(format!(
+                        "Target {} ({})",
+                        target.current_point.name, target.current_point.container_name
+                    ));
+                }
+            }
+            for path in self.global_paths.values() {
+                let hidden = path
+                    .history
+                    .iter()
+                    .filter(|p| p.container_name != self.map_container)
+                    .count();
+                if path.map_displayed & (hidden > 0) {
+                    off_container.push(format!(
+                        "Path {}: {hidden}/{} points",
+                        path.name,
+                        path.history.len()
+                    ));
+                }
+            }
+            if !off_container.is_empty() {
+                CollapsingHeader::new(format!(
+                    "Not on {} ({})",
+                    self.map_container,
+                    off_container.len()
+                ))
+                .id_source("OffContainer")
+                .show(ui, |ui| {
+                    for item in off_container {
+                        ui.label(item);
+                    }
+                });
+            }
+
             // TODO get map from scdatatools
             let plot_response = Plot::new("my_plot")
                 .data_aspect(1.0)
@@ -472,9 +1726,7 @@ impl MyEguiApp {
                 .include_y(90)
                 .include_y(-90)
                 .label_formatter(|name, value| {
-                    unsafe {
-                        SMARTY = name.to_string();
-                    }
+                    *SMARTY.lock().unwrap() = name.to_string();
                     if !name.is_empty() {
                         format!(
                             "{name}\n{}\n{}",
@@ -486,12 +1738,21 @@ impl MyEguiApp {
                     }
                 })
                 .show(ui, |plot_ui| {
+                    // Local coordinates on displayed container to map
+                    let map_container = self.database.get(&self.map_container);
+                    let to_map = |local: Vec3d| {
+                        map_point(
+                            map_container
+                                .map_or(Geodetic::UNDEFINED, |c| c.local_to_geodetic(local)),
+                        )
+                    };
+
                     // Draw Targets in map
                     for target in &self.global_targets {
-                        let c = [
-                            target.current_point.longitude.to_degrees(),
-                            target.current_point.latitude.to_degrees(),
-                        ];
+                        if target.current_point.container_name != self.map_container {
+                            continue;
+                        }
+                        let c = to_map(target.current_point.local_coordinates);
                         plot_ui.points(
                             Points::new(c)
                                 .name(&target.current_point.name)
@@ -501,18 +1762,34 @@ impl MyEguiApp {
                         );
                     }
 
+                    // Draw optimized visit order in map
+                    if self.route.visit_container == self.map_container {
+                        let route_line: Vec<[f64; 2]> =
+                            self.route.visit_route.iter().map(|c| to_map(*c)).collect();
+                        plot_ui.line(
+                            Line::new(route_line)
+                                .name("Visit order")
+                                .width(1.5)
+                                .color(Color32::GOLD)
+                                .style(LineStyle::dotted_loose()),
+                        );
+                    }
+
                     // Draw Paths in map
                     for path in self.global_paths.values() {
                         if path.map_displayed {
-                            // Accumulator
-                            let mut path_line = Vec::new();
+                            // Accumulator, one line per segment on displayed container
+                            let mut path_lines = vec![Vec::new()];
                             let mut path_point = Vec::new();
                             for (index, point) in path.history.iter().enumerate() {
-                                let c = [
-                                    point.local_coordinates.longitude().to_degrees(),
-                                    point.local_coordinates.latitude().to_degrees(),
-                                ];
-                                path_line.push(c);
+                                if point.container_name != self.map_container {
+                                    if !path_lines.last().unwrap().is_empty() {
+                                        path_lines.push(Vec::new());
+                                    }
+                                    continue;
+                                }
+                                let c = to_map(point.local_coordinates);
+                                path_lines.last_mut().unwrap().push(c);
                                 path_point.push(if path.current_index == index + 1 {
                                     let highlight_color = Color32::from_rgb(
                                         255 - path.map_color.r(),
@@ -536,22 +1813,75 @@ impl MyEguiApp {
                             for point in path_point {
                                 plot_ui.points(point);
                             }
-                            plot_ui.line(
-                                Line::new(path_line)
-                                    .name(&path.name) //BUG highlight point get pathname on display, side effect frow drawing path...
-                                    .width(1.5)
-                                    .color(path.map_color),
-                            );
+                            // Error circle around each capture, a hollow marker sized in pixels
+                            let pixels_per_degree = plot_ui.transform().dpos_dvalue_y().abs();
+                            for point in &path.history {
+                                if point.container_name != self.map_container {
+                                    continue;
+                                }
+                                let radius = point
+                                    .uncertainty(&self.database, self.settings.capture_latency);
+                                if let Some(angle) = error_angle(point, radius, &self.database) {
+                                    plot_ui.points(
+                                        Points::new(to_map(point.local_coordinates))
+                                            .radius((angle * pixels_per_degree) as f32)
+                                            .filled(false)
+                                            .color(path.map_color.gamma_multiply(0.5)),
+                                    );
+                                }
+                            }
+                            for path_line in path_lines {
+                                plot_ui.line(
+                                    Line::new(path_line)
+                                        .name(&path.name) //BUG highlight point get pathname on display, side effect frow drawing path...
+                                        .width(1.5)
+                                        .color(path.map_color),
+                                );
+                            }
                         }
                     }
+
+                    // Draw Replay marker in map
+                    if let Some(position) = self
+                        .replay
+                        .position
+                        .as_ref()
+                        .filter(|p| p.container_name == self.map_container)
+                    {
+                        plot_ui.points(
+                            Points::new(to_map(position.local_coordinates))
+                                .name(&position.name)
+                                .radius(6.0)
+                                .shape(MarkerShape::Asterisk)
+                                .color(Color32::WHITE),
+                        );
+                    }
+
+                    // Draw Path Tools preview in map
+                    let preview_line: Vec<[f64; 2]> = self
+                        .path_tools
+                        .preview
+                        .iter()
+                        .filter(|point| point.container_name == self.map_container)
+                        .map(|point| to_map(point.local_coordinates))
+                        .collect();
+                    if !preview_line.is_empty() {
+                        plot_ui.points(
+                            Points::new(preview_line.clone())
+                                .radius(2.0)
+                                .color(Color32::WHITE),
+                        );
+                        plot_ui.line(
+                            Line::new(preview_line)
+                                .style(LineStyle::dashed_dense())
+                                .width(1.0)
+                                .color(Color32::WHITE),
+                        );
+                    }
                 });
 
             // Handle interaction
-            let snapped_point: String;
-            unsafe {
-                snapped_point = SMARTY.clone();
-                SMARTY = String::new();
-            }
+            let snapped_point = std::mem::take(&mut *SMARTY.lock().unwrap());
             if plot_response
                 .response
                 .clicked_by(egui::PointerButton::Primary)
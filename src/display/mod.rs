@@ -17,12 +17,12 @@
 
 mod main_app;
 use crate::prelude::*;
-pub use main_app::*;
 
 use chrono::Duration;
-use egui::{Color32, Context, Pos2};
+use egui::{Color32, Context, Pos2, RichText};
+use egui_plot::{Line, Plot, VLine};
 use rand::Rng;
-use std::f64::{consts::PI, NAN};
+use std::f64::consts::PI;
 
 /// Take radian value and outup a nice 00° 00’ 00” string
 pub fn pretty(a: f64) -> String {
@@ -42,6 +42,15 @@ pub fn pretty_duration(a: Duration) -> String {
     format!("{:0>2}:{:0>2}:{:0>2}", hours, minutes, seconds)
 }
 
+/// Duration in seconds as 00:00:00, or – when unknown
+pub fn pretty_seconds(seconds: f64) -> String {
+    if seconds.is_finite() {
+        pretty_duration(Duration::milliseconds((seconds * 1000.0) as i64))
+    } else {
+        "–".to_string()
+    }
+}
+
 // pub fn legend(a: f64, b: usize, range: &RangeInclusive<f64>) -> String {
 //     let degrees = a.to_degrees().trunc();
 //     format!("{degrees}°")
@@ -80,6 +89,42 @@ pub fn random_color32() -> Color32 {
     )
 }
 
+/// Draw a profile y(x) of a path, mark highlighted point, return index of hovered point
+fn profile_plot(
+    ui: &mut egui::Ui,
+    name: String,
+    x: &[f64],
+    y: &[f64],
+    current_index: usize,
+) -> Option<usize> {
+    let profile: Vec<[f64; 2]> = x
+        .iter()
+        .zip(y)
+        .filter(|(_, y)| !y.is_nan())
+        .map(|(x, y)| [*x, *y])
+        .collect();
+    let plot_response = Plot::new(&name)
+        .height(120.0)
+        .allow_scroll(false)
+        .show(ui, |plot_ui| {
+            plot_ui.line(Line::new(profile).name(&name));
+            // index = 0 mean no highlight !
+            if let Some(x) = current_index.checked_sub(1).and_then(|i| x.get(i)) {
+                plot_ui.vline(VLine::new(*x).color(Color32::WHITE));
+            }
+            plot_ui.pointer_coordinate()
+        });
+
+    if !plot_response.response.hovered() {
+        return None;
+    }
+    let pointer = plot_response.inner?;
+    x.iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| (*a - pointer.x).abs().total_cmp(&(*b - pointer.x).abs()))
+        .map(|(index, _)| index)
+}
+
 impl Target {
     pub fn display(&mut self, ctx: &Context) {
         egui::Window::new(format!(
@@ -100,15 +145,146 @@ impl Target {
                 ui.label(format!("{:.3}km", self.current_point.altitude));
                 ui.end_row();
                 ui.label("Distance:");
-                ui.label(format!("{:.3}km", self.current_distance));
+                ui.label(format!(
+                    "{:.3}km ±{:.3}",
+                    self.current_geometry.distance, self.current_geometry.distance_error
+                ));
+                ui.end_row();
+                ui.label("Surface distance:");
+                ui.label(format!("{:.3}km", self.current_geometry.surface_distance));
+                ui.end_row();
+                if self.current_geometry.frame != Frame::Absolute {
+                    ui.label("Elevation:");
+                    ui.label(pretty(self.current_geometry.elevation));
+                    ui.end_row();
+                }
+                ui.label("Line of sight:");
+                match &self.current_geometry.occluded_by {
+                    Some(body) => {
+                        ui.label(RichText::new(format!("Hidden by {body}")).color(Color32::RED))
+                    }
+                    None => ui.label(RichText::new("Visible").color(Color32::GREEN)),
+                };
                 ui.end_row();
-                ui.label("Heading:");
-                ui.label(pretty(self.current_heading));
+                ui.label("QT ETA:");
+                ui.label(pretty_seconds(self.qt_eta.unwrap_or(f64::NAN)));
                 ui.end_row();
-                ui.label("CIG Heading:");
-                ui.label(borked_cig_heading(self.current_heading));
+                ui.label("Closing speed:");
+                ui.label(format!("{:.1}m/s", self.closing_speed));
+                ui.end_row();
+                if self.current_geometry.frame != Frame::Absolute {
+                    ui.label("Heading:");
+                    ui.label(format!(
+                        "{} ±{}",
+                        pretty(self.current_geometry.bearing),
+                        pretty(self.current_geometry.bearing_error)
+                    ));
+                    ui.end_row();
+                    ui.label("CIG Heading:");
+                    ui.label(borked_cig_heading(self.current_geometry.bearing));
+                    ui.end_row();
+                }
+                ui.label("Frame:");
+                ui.label(self.current_geometry.frame.description());
                 ui.end_row();
             });
+
+            // Aim in space with angles against reference bodies
+            if !self.space_references.is_empty() {
+                ui.separator();
+                egui::Grid::new("SpaceReferences")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Reference");
+                        ui.label("Separation");
+                        ui.label("Distance");
+                        ui.end_row();
+                        for reference in &self.space_references {
+                            ui.label(&reference.name);
+                            ui.label(format!("{:.2}°", reference.separation));
+                            ui.label(format!("{:.0}km", reference.distance));
+                            ui.end_row();
+                        }
+                    });
+            }
+
+            ui.collapsing("Predict", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("In");
+                    ui.add(
+                        egui::DragValue::new(&mut self.predict_offset)
+                            .speed(1.0)
+                            .suffix("s"),
+                    );
+                    if ui
+                        .add_enabled(self.qt_eta.is_some(), egui::Button::new("QT ETA"))
+                        .clicked()
+                    {
+                        self.predict_offset = self.qt_eta.unwrap();
+                    };
+                });
+                let prediction = self.prediction.as_ref();
+                egui::Grid::new("Prediction").show(ui, |ui| {
+                    let coordinates = prediction.map(|p| p.coordinates);
+                    ui.label("Position:");
+                    ui.label(
+                        coordinates
+                            .map(|c| format!("{:.3} {:.3} {:.3}km", c.x, c.y, c.z))
+                            .unwrap_or_default(),
+                    );
+                    ui.end_row();
+                    ui.label("Distance:");
+                    ui.label(format!(
+                        "{:.3}km",
+                        prediction.map(|p| p.distance).unwrap_or(f64::NAN)
+                    ));
+                    ui.end_row();
+                    ui.label("Heading:");
+                    ui.label(pretty(prediction.map(|p| p.bearing).unwrap_or(f64::NAN)));
+                    ui.end_row();
+                    ui.label("Under star in:");
+                    ui.label(
+                        self.subsolar_time
+                            .map(|t| pretty_duration(Duration::seconds(t as i64)))
+                            .unwrap_or("never".to_string()),
+                    );
+                    ui.end_row();
+                    ui.add(
+                        egui::DragValue::new(&mut self.predict_longitude)
+                            .speed(0.5)
+                            .clamp_range(-180.0..=180.0)
+                            .prefix("Under ")
+                            .suffix("° in:"),
+                    );
+                    ui.label(
+                        self.longitude_time
+                            .map(|t| pretty_duration(Duration::seconds(t as i64)))
+                            .unwrap_or("never".to_string()),
+                    );
+                    ui.end_row();
+                });
+            });
+
+            if !self.qt_candidates.is_empty() {
+                ui.collapsing("QT markers", |ui| {
+                    egui::Grid::new("QtCandidates")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label("Marker");
+                            ui.label("Jump");
+                            ui.label("Final leg");
+                            ui.label("Heading");
+                            ui.end_row();
+                            for candidate in self.qt_candidates.iter().take(5) {
+                                ui.label(&candidate.name);
+                                ui.label(format!("{:.0}s", candidate.jump_time));
+                                ui.label(format!("{:.3}km", candidate.remaining));
+                                ui.label(pretty(candidate.bearing));
+                                ui.end_row();
+                            }
+                        });
+                });
+            }
         });
     }
 }
@@ -143,26 +319,50 @@ impl Path {
                             .unwrap_or_default(),
                     );
                     ui.end_row();
+                    ui.label("Container:");
+                    ui.label(
+                        current_point
+                            .map(|p| p.container_name.to_string())
+                            .unwrap_or_default(),
+                    );
+                    ui.end_row();
                     ui.label("Latitute:");
-                    ui.label(pretty(current_point.map(|p| p.latitude).unwrap_or(NAN)));
+                    ui.label(pretty(
+                        current_point.map(|p| p.latitude).unwrap_or(f64::NAN),
+                    ));
                     ui.end_row();
                     ui.label("Longitude:");
-                    ui.label(pretty(current_point.map(|p| p.longitude).unwrap_or(NAN)));
+                    ui.label(pretty(
+                        current_point.map(|p| p.longitude).unwrap_or(f64::NAN),
+                    ));
                     ui.end_row();
                     ui.label("Altitude:");
                     ui.label(format!(
                         "{:.3}km",
-                        current_point.map(|p| p.altitude).unwrap_or(NAN)
+                        current_point.map(|p| p.altitude).unwrap_or(f64::NAN)
                     ));
                     ui.end_row();
                     ui.label("Distance:");
-                    ui.label(format!("{:.3}km", self.current_distance));
+                    ui.label(format!(
+                        "{:.3}km ±{:.3}",
+                        self.current_geometry.distance, self.current_geometry.distance_error
+                    ));
+                    ui.end_row();
+                    ui.label("Surface distance:");
+                    ui.label(format!("{:.3}km", self.current_geometry.surface_distance));
                     ui.end_row();
                     ui.label("Heading:");
-                    ui.label(pretty(self.current_heading));
+                    ui.label(format!(
+                        "{} ±{}",
+                        pretty(self.current_geometry.bearing),
+                        pretty(self.current_geometry.bearing_error)
+                    ));
                     ui.end_row();
                     ui.label("CIG Heading:");
-                    ui.label(borked_cig_heading(self.current_heading));
+                    ui.label(borked_cig_heading(self.current_geometry.bearing));
+                    ui.end_row();
+                    ui.label("Frame:");
+                    ui.label(self.current_geometry.frame.description());
                     ui.end_row();
                     ui.label("Duration:");
                     ui.label(pretty_duration(self.duration));
@@ -171,6 +371,54 @@ impl Path {
                     ui.label(format!("{:.3}km", self.length));
                     ui.end_row();
                 });
+
+                // Container segments, clic to highlight first point
+                let segments = path_segments(&self.history);
+                if segments.len() > 1 {
+                    egui::CollapsingHeader::new(format!("Containers ({})", segments.len()))
+                        .id_source(format!("Containers {}", self.name))
+                        .show(ui, |ui| {
+                            egui::Grid::new("Containers").show(ui, |ui| {
+                                for segment in segments {
+                                    if ui.link(&segment.container_name).clicked() {
+                                        self.current_index = segment.start + 1;
+                                    };
+                                    ui.label(format!(
+                                        "{}-{}",
+                                        segment.start + 1,
+                                        segment.start + segment.count
+                                    ));
+                                    ui.label(format!("{:.3}km", segment.length));
+                                    ui.end_row();
+                                }
+                            });
+                        });
+                }
+
+                // Profiles, hover sync highlighted point
+                ui.separator();
+                ui.checkbox(&mut self.widget_speed_profile, "Speed profile");
+                let altitudes: Vec<f64> = self.history.iter().map(|p| p.altitude).collect();
+                if let Some(index) = profile_plot(
+                    ui,
+                    format!("Altitude {}", self.name),
+                    &cumulative_distance(&self.history),
+                    &altitudes,
+                    self.current_index,
+                ) {
+                    self.current_index = index + 1;
+                }
+                if self.widget_speed_profile {
+                    if let Some(index) = profile_plot(
+                        ui,
+                        format!("Speed {}", self.name),
+                        &cumulative_time(&self.history),
+                        &path_speeds(&self.history),
+                        self.current_index,
+                    ) {
+                        self.current_index = index + 1;
+                    }
+                }
             });
     }
 }
@@ -0,0 +1,554 @@
+// Weoo, a StarCitizen navigation tool
+// Copyright (C) 2024 Benoît Fournier benoit.fournier@clever-cloud.com
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+
+use crate::prelude::*;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Custom POI store, one `<container>/<name>.json` file per POI
+const CUSTOM_POI_DIRECTORY: &str = "pois";
+/// Former custom POI store, a single map of all POIs, still read until migrated
+const CUSTOM_POI_LEGACY_FILE: &str = "CustomPoi.json";
+
+/// Custom POI identity, (container, name)
+pub type PoiKey = (String, String);
+
+pub fn poi_key(poi: &Poi) -> PoiKey {
+    (poi.container.clone(), poi.name.clone())
+}
+
+/// Custom POIs of pois directory and CustomPoi.json, with the file each one was read from
+#[derive(Debug, Default)]
+pub struct CustomPoiStore {
+    /// Directory holding CustomPoi.json and pois directory
+    root: PathBuf,
+    /// POIs of pois directory, with their actual file
+    files: BTreeMap<PoiKey, (PathBuf, Poi)>,
+    /// POIs of CustomPoi.json, shadowed by pois directory
+    legacy: BTreeMap<PoiKey, Poi>,
+}
+
+/// Outcome of CustomPoi.json migration
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    pub migrated: usize,
+    /// Already in pois directory with same content
+    pub unchanged: usize,
+    /// Already in pois directory with a different content, left in CustomPoi.json
+    pub conflicts: Vec<PoiKey>,
+    pub errors: Vec<String>,
+    /// Where CustomPoi.json was moved, once fully migrated
+    pub backup: Option<PathBuf>,
+}
+
+impl std::fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} migrated, {} unchanged, {} conflicts",
+            self.migrated,
+            self.unchanged,
+            self.conflicts.len()
+        )?;
+        for (container, name) in &self.conflicts {
+            write!(
+                f,
+                "\nConflict: {name} ({container}) differs in {CUSTOM_POI_LEGACY_FILE}"
+            )?;
+        }
+        for error in &self.errors {
+            write!(f, "\n{error}")?;
+        }
+        match &self.backup {
+            Some(backup) => write!(
+                f,
+                "\n{CUSTOM_POI_LEGACY_FILE} moved to {}",
+                backup.display()
+            ),
+            None => write!(f, "\n{CUSTOM_POI_LEGACY_FILE} kept"),
+        }
+    }
+}
+
+/// Name without characters forbidden in file names on some file systems
+fn sanitize_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim_end_matches(['.', ' ']);
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Paths compare case insensitively, as on Windows
+fn path_id(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+/// First numbered candidate not on disk and accepted by `free`
+fn first_free(candidate: impl Fn(usize) -> PathBuf, free: impl Fn(&Path) -> bool) -> PathBuf {
+    let mut i = 1;
+    loop {
+        let path = candidate(i);
+        if free(&path) && !path.exists() {
+            return path;
+        }
+        i += 1;
+    }
+}
+
+fn read_legacy_file(path: &Path) -> Option<BTreeMap<String, Poi>> {
+    let file = File::open(path).ok()?;
+    match serde_json::from_reader(file) {
+        Ok(pois) => Some(pois),
+        Err(_) => {
+            println!("Fail to parse {}, incorrect format", path.display());
+            None
+        }
+    }
+}
+
+fn write_json<T: serde::Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Fail to create {}: {e}", parent.display()))?;
+    }
+    let mut content = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Fail to serialize {}: {e}", path.display()))?;
+    content.push('\n');
+    fs::write(path, content).map_err(|e| format!("Fail to write {}: {e}", path.display()))
+}
+
+impl CustomPoiStore {
+    /// Read CustomPoi.json and pois directory of working directory
+    pub fn load() -> Self {
+        Self::load_in(PathBuf::new())
+    }
+
+    /// Read CustomPoi.json and pois directory of root
+    pub fn load_in(root: PathBuf) -> Self {
+        let mut store = Self {
+            root,
+            ..Default::default()
+        };
+        for poi in read_legacy_file(&store.legacy_file())
+            .into_iter()
+            .flat_map(|pois| pois.into_values())
+        {
+            store.legacy.insert(poi_key(&poi), poi);
+        }
+
+        let Ok(containers) = fs::read_dir(store.root.join(CUSTOM_POI_DIRECTORY)) else {
+            return store;
+        };
+        let mut files: Vec<PathBuf> = containers
+            .flatten()
+            .filter_map(|container| fs::read_dir(container.path()).ok())
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == "json"))
+            .collect();
+        files.sort();
+        for file in files {
+            let Ok(content) = fs::read_to_string(&file) else {
+                continue;
+            };
+            let Ok(poi) = serde_json::from_str::<Poi>(&content) else {
+                println!("Fail to parse {}, incorrect format", file.display());
+                continue;
+            };
+            match store.files.get(&poi_key(&poi)) {
+                Some((kept, _)) => println!(
+                    "Ignore {}, {} ({}) already read from {}",
+                    file.display(),
+                    poi.name,
+                    poi.container,
+                    kept.display()
+                ),
+                None => {
+                    store.files.insert(poi_key(&poi), (file, poi));
+                }
+            }
+        }
+        store
+    }
+
+    /// All custom POIs, pois directory shadowing CustomPoi.json
+    pub fn pois(&self) -> impl Iterator<Item = &Poi> {
+        self.legacy
+            .iter()
+            .filter(|(key, _)| !self.files.contains_key(*key))
+            .map(|(_, poi)| poi)
+            .chain(self.files.values().map(|(_, poi)| poi))
+    }
+
+    pub fn get(&self, container: &str, name: &str) -> Option<&Poi> {
+        let key = (container.to_string(), name.to_string());
+        self.files
+            .get(&key)
+            .map(|(_, poi)| poi)
+            .or_else(|| self.legacy.get(&key))
+    }
+
+    fn legacy_file(&self) -> PathBuf {
+        self.root.join(CUSTOM_POI_LEGACY_FILE)
+    }
+
+    /// CustomPoi.json still holds POIs
+    pub fn has_legacy(&self) -> bool {
+        !self.legacy.is_empty()
+    }
+
+    /// First free `<container>/<name>.json`, suffixed when another POI sanitizes to it
+    fn new_path(&self, poi: &Poi) -> PathBuf {
+        let taken: HashSet<String> = self.files.values().map(|(path, _)| path_id(path)).collect();
+        let directory = self
+            .root
+            .join(CUSTOM_POI_DIRECTORY)
+            .join(sanitize_file_name(&poi.container));
+        let stem = sanitize_file_name(&poi.name);
+        first_free(
+            |i| match i {
+                1 => directory.join(format!("{stem}.json")),
+                i => directory.join(format!("{stem} ({i}).json")),
+            },
+            |path| !taken.contains(&path_id(path)),
+        )
+    }
+
+    fn write_file(&mut self, poi: &Poi) -> Result<(), String> {
+        let key = poi_key(poi);
+        let path = match self.files.get(&key) {
+            Some((path, _)) => path.clone(),
+            None => self.new_path(poi),
+        };
+        write_json(&path, poi)?;
+        self.files.insert(key, (path, poi.clone()));
+        Ok(())
+    }
+
+    /// Drop POIs from CustomPoi.json
+    fn remove_legacy(&mut self, keys: &[PoiKey]) -> Result<(), String> {
+        if !keys.iter().any(|key| self.legacy.contains_key(key)) {
+            return Ok(());
+        }
+        let legacy_file = self.legacy_file();
+        let mut pois = read_legacy_file(&legacy_file)
+            .ok_or_else(|| format!("Fail to read {}", legacy_file.display()))?;
+        pois.retain(|_, poi| !keys.contains(&poi_key(poi)));
+        write_json(&legacy_file, &pois)?;
+        for key in keys {
+            self.legacy.remove(key);
+        }
+        Ok(())
+    }
+
+    /// Add or replace a POI, in its own file of pois directory
+    pub fn save(&mut self, poi: &Poi) {
+        if let Err(e) = self
+            .write_file(poi)
+            .and_then(|_| self.remove_legacy(&[poi_key(poi)]))
+        {
+            println!("{e}");
+        }
+    }
+
+    /// Remove a POI, from the file it was read from
+    pub fn delete(&mut self, container: &str, name: &str) {
+        let key = (container.to_string(), name.to_string());
+        if let Some((path, _)) = self.files.remove(&key) {
+            match fs::remove_file(&path) {
+                // Drop container directory once empty
+                Ok(_) => {
+                    let _ = path.parent().map(fs::remove_dir);
+                }
+                Err(e) => println!("Fail to remove {}: {e}", path.display()),
+            }
+        }
+        if let Err(e) = self.remove_legacy(&[key]) {
+            println!("{e}");
+        }
+    }
+
+    /// Move POIs of CustomPoi.json into pois directory, keeping CustomPoi.json while in conflict
+    pub fn migrate_legacy(&mut self) -> MigrationReport {
+        let mut report = MigrationReport::default();
+        for (key, poi) in self.legacy.clone() {
+            match self.files.get(&key) {
+                Some((_, existing)) if *existing == poi => report.unchanged += 1,
+                Some(_) => report.conflicts.push(key),
+                None => match self.write_file(&poi) {
+                    Ok(_) => report.migrated += 1,
+                    Err(e) => report.errors.push(e),
+                },
+            }
+        }
+        if !report.conflicts.is_empty() || !report.errors.is_empty() || !self.has_legacy() {
+            return report;
+        }
+
+        let backup = first_free(
+            |i| match i {
+                1 => self.root.join(format!("{CUSTOM_POI_LEGACY_FILE}.bak")),
+                i => self.root.join(format!("{CUSTOM_POI_LEGACY_FILE}.bak.{i}")),
+            },
+            |_| true,
+        );
+        match fs::rename(self.legacy_file(), &backup) {
+            Ok(_) => {
+                self.legacy.clear();
+                report.backup = Some(backup);
+            }
+            Err(e) => report
+                .errors
+                .push(format!("Fail to move {CUSTOM_POI_LEGACY_FILE}: {e}")),
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use uuid::Uuid;
+
+    /// Fresh directory, removed when dropped
+    struct TempRoot(PathBuf);
+
+    impl TempRoot {
+        fn new() -> Self {
+            let path = std::env::temp_dir().join(format!("weoo-test-{}", Uuid::new_v4()));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempRoot {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn poi(container: &str, name: &str, x: f64) -> Poi {
+        Poi {
+            name: name.to_string(),
+            container: container.to_string(),
+            coordinates: Vec3d::new(x, 0.0, 0.0),
+            ..Default::default()
+        }
+    }
+
+    fn write_legacy(root: &Path, pois: &[Poi]) {
+        let pois: BTreeMap<String, Poi> =
+            pois.iter().map(|p| (p.name.clone(), p.clone())).collect();
+        write_json(&root.join(CUSTOM_POI_LEGACY_FILE), &pois).unwrap();
+    }
+
+    fn files(root: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(root.join(CUSTOM_POI_DIRECTORY))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|container| fs::read_dir(container.path()).ok())
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn sanitize() {
+        assert_eq!(sanitize_file_name("A/B"), "A_B");
+        assert_eq!(sanitize_file_name("a:b*c?\"<>|\\"), "a_b_c______");
+        assert_eq!(sanitize_file_name("Foo. "), "Foo");
+        assert_eq!(sanitize_file_name("..."), "_");
+        assert_eq!(sanitize_file_name(""), "_");
+    }
+
+    #[test]
+    fn save_load_delete() {
+        let root = TempRoot::new();
+        let mut store = CustomPoiStore::load_in(root.0.clone());
+        assert_eq!(store.pois().count(), 0);
+
+        store.save(&poi("Daymar", "Cave", 1.0));
+        // Override keeps the same file
+        store.save(&poi("Daymar", "Cave", 2.0));
+        assert_eq!(files(&root.0).len(), 1);
+
+        let store_reloaded = CustomPoiStore::load_in(root.0.clone());
+        assert_eq!(
+            store_reloaded.get("Daymar", "Cave"),
+            Some(&poi("Daymar", "Cave", 2.0))
+        );
+        assert_eq!(store_reloaded.get("Yela", "Cave"), None);
+
+        store.delete("Daymar", "Cave");
+        assert_eq!(store.get("Daymar", "Cave"), None);
+        assert!(files(&root.0).is_empty());
+        // Empty container directory is dropped
+        assert!(!root.0.join(CUSTOM_POI_DIRECTORY).join("Daymar").exists());
+        assert_eq!(CustomPoiStore::load_in(root.0.clone()).pois().count(), 0);
+    }
+
+    #[test]
+    fn colliding_file_names() {
+        let root = TempRoot::new();
+        let mut store = CustomPoiStore::load_in(root.0.clone());
+        let pois = [
+            poi("Daymar", "A/B", 1.0),
+            poi("Daymar", "A_B", 2.0),
+            poi("Daymar", "Foo.", 3.0),
+            poi("Daymar", "Foo", 4.0),
+            poi("Daymar", "foo", 5.0),
+        ];
+        for poi in &pois {
+            store.save(poi);
+        }
+        assert_eq!(files(&root.0).len(), pois.len());
+
+        let mut reloaded = CustomPoiStore::load_in(root.0.clone());
+        for poi in &pois {
+            assert_eq!(reloaded.get(&poi.container, &poi.name), Some(poi));
+        }
+
+        // Delete remove the file each POI was read from
+        reloaded.delete("Daymar", "A_B");
+        reloaded.delete("Daymar", "Foo");
+        let reloaded = CustomPoiStore::load_in(root.0.clone());
+        let mut names: Vec<&str> = reloaded.pois().map(|p| p.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["A/B", "Foo.", "foo"]);
+    }
+
+    #[test]
+    fn file_name_is_not_identity() {
+        let root = TempRoot::new();
+        // Written by hand, name does not match file
+        let path = root
+            .0
+            .join(CUSTOM_POI_DIRECTORY)
+            .join("Daymar")
+            .join("any.json");
+        write_json(&path, &poi("Daymar", "Cave", 1.0)).unwrap();
+
+        let mut store = CustomPoiStore::load_in(root.0.clone());
+        store.save(&poi("Daymar", "Cave", 2.0));
+        assert_eq!(files(&root.0), vec![path.clone()]);
+        store.delete("Daymar", "Cave");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn legacy_shadowed_by_directory() {
+        let root = TempRoot::new();
+        write_legacy(
+            &root.0,
+            &[poi("Daymar", "Cave", 1.0), poi("Daymar", "Outpost", 1.0)],
+        );
+        write_json(
+            &root
+                .0
+                .join(CUSTOM_POI_DIRECTORY)
+                .join("Daymar")
+                .join("Cave.json"),
+            &poi("Daymar", "Cave", 2.0),
+        )
+        .unwrap();
+
+        let mut store = CustomPoiStore::load_in(root.0.clone());
+        assert!(store.has_legacy());
+        assert_eq!(store.pois().count(), 2);
+        assert_eq!(
+            store.get("Daymar", "Cave"),
+            Some(&poi("Daymar", "Cave", 2.0))
+        );
+
+        // Legacy POI does not come back after delete
+        store.delete("Daymar", "Outpost");
+        let reloaded = CustomPoiStore::load_in(root.0.clone());
+        assert_eq!(reloaded.get("Daymar", "Outpost"), None);
+        assert_eq!(reloaded.pois().count(), 1);
+    }
+
+    #[test]
+    fn migrate_report_conflicts() {
+        let root = TempRoot::new();
+        write_legacy(
+            &root.0,
+            &[
+                poi("Daymar", "Same", 1.0),
+                poi("Daymar", "New", 1.0),
+                poi("Daymar", "Conflict", 1.0),
+            ],
+        );
+        let directory = root.0.join(CUSTOM_POI_DIRECTORY).join("Daymar");
+        write_json(&directory.join("Same.json"), &poi("Daymar", "Same", 1.0)).unwrap();
+        write_json(
+            &directory.join("Conflict.json"),
+            &poi("Daymar", "Conflict", 2.0),
+        )
+        .unwrap();
+        let backup = root.0.join(format!("{CUSTOM_POI_LEGACY_FILE}.bak"));
+        fs::write(&backup, "older backup").unwrap();
+
+        let mut store = CustomPoiStore::load_in(root.0.clone());
+        let report = store.migrate_legacy();
+        assert_eq!(report.migrated, 1);
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(
+            report.conflicts,
+            vec![("Daymar".to_string(), "Conflict".to_string())]
+        );
+        assert_eq!(report.backup, None);
+        assert!(root.0.join(CUSTOM_POI_LEGACY_FILE).exists());
+        // Conflicting file is left as is
+        assert_eq!(
+            store.get("Daymar", "Conflict"),
+            Some(&poi("Daymar", "Conflict", 2.0))
+        );
+
+        // Once solved, legacy file is kept next to existing backup
+        store.save(&poi("Daymar", "Conflict", 2.0));
+        let report = store.migrate_legacy();
+        assert!(report.conflicts.is_empty());
+        assert_eq!(
+            report.backup,
+            Some(root.0.join(format!("{CUSTOM_POI_LEGACY_FILE}.bak.2")))
+        );
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "older backup");
+        assert!(!root.0.join(CUSTOM_POI_LEGACY_FILE).exists());
+        assert!(!store.has_legacy());
+
+        let reloaded = CustomPoiStore::load_in(root.0.clone());
+        assert_eq!(reloaded.pois().count(), 3);
+        assert!(!reloaded.has_legacy());
+    }
+}
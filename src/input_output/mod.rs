@@ -15,19 +15,67 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //
 
-use crate::prelude::*;
+mod custom_poi_store;
+pub use custom_poi_store::*;
+
+use crate::{prelude::*, REFERENCE_TIME};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File};
 
-pub fn load_database() -> BTreeMap<String, Container> {
+/// User settings, stored in Settings.json
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// Seconds added to local clock to get game time
+    pub clock_offset: f64,
+    /// Name of quantum drive used for ETA
+    pub qt_drive: String,
+    /// Seconds between position shown in game and its capture
+    pub capture_latency: f64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            clock_offset: 0.0,
+            qt_drive: String::new(),
+            capture_latency: 0.5,
+        }
+    }
+}
+
+/// Local correction of a container, stored in DatabaseOverride.json and applied over Database.json
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+pub struct ContainerOverride {
+    pub rotation_adjust: Option<f64>,
+    pub rotation_speed: Option<f64>,
+}
+
+/// Containers of Database.json with custom POIs and overrides, and reference epoch of rotations
+pub fn load_database(custom_pois: &CustomPoiStore) -> (BTreeMap<String, Container>, DateTime<Utc>) {
     // Database.json
     let file = fs::File::open("Database.json").expect("file should open read only");
-    let json: HashMap<String, HashMap<String, HashMap<String, serde_json::Value>>> =
+    let json: HashMap<String, serde_json::Value> =
         serde_json::from_reader(file).expect("file should be proper JSON");
 
+    let reference_time = json
+        .get("Reference Time")
+        .and_then(|v| v.as_str())
+        .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+        .map(|v| v.with_timezone(&Utc))
+        .unwrap_or(*REFERENCE_TIME);
+
     let mut containers: BTreeMap<String, Container> = BTreeMap::new();
 
-    for (_k, v) in json.iter() {
+    for (_k, v) in json.into_iter() {
+        // Skip database metadata like "Reference Time"
+        let Ok(v) =
+            serde_json::from_value::<HashMap<String, HashMap<String, serde_json::Value>>>(v)
+        else {
+            continue;
+        };
         // println!("keys : {k}");
         for (_kk, vv) in v.iter() {
             // println!("kkeys {kk}");
@@ -61,15 +109,16 @@ pub fn load_database() -> BTreeMap<String, Container> {
                             .parse()
                             .unwrap(),
                     ),
-
-                    latitude: Some(coordinates.latitude()),
-                    longitude: Some(coordinates.longitude()),
+                    latitude: None,
+                    longitude: None,
                     altitude: None,
+                    samples: None,
+                    spread: None,
                 };
                 poi.insert(new_poi.name.clone(), new_poi);
             }
 
-            let elem = Container {
+            let mut elem = Container {
                 name: vv.get("Name").unwrap().to_string().replace('"', ""),
                 coordinates: Vec3d::new(
                     vv.get("X").unwrap().as_f64().unwrap(),
@@ -100,30 +149,92 @@ pub fn load_database() -> BTreeMap<String, Container> {
                 orbital_speed: vv.get("Orbital Speed").unwrap().as_f64().unwrap(),
                 orbital_angle: vv.get("Orbital Angle").unwrap().as_f64().unwrap(),
                 grid_radius: vv.get("Grid Radius").unwrap().as_f64().unwrap(),
-                poi,
+                poi: BTreeMap::new(),
             };
+            // Geodetic of POIs on container body, none in Space
+            for new_poi in poi.values_mut() {
+                let geodetic = elem.local_to_geodetic(new_poi.coordinates);
+                new_poi.latitude = Some(geodetic.latitude).filter(|v| v.is_finite());
+                new_poi.longitude = Some(geodetic.longitude).filter(|v| v.is_finite());
+                new_poi.altitude = Some(geodetic.altitude).filter(|v| v.is_finite());
+            }
+            elem.poi = poi;
             containers.insert(elem.name.clone(), elem);
         }
     }
 
-    // CustomPoi.json
-    if let Ok(file) = File::open("CustomPoi.json") {
-        let json: BTreeMap<String, Poi> =
-            serde_json::from_reader(file).expect("file should be proper JSON");
+    // Custom POIs
+    for poi in custom_pois.pois() {
+        if let Some(container) = containers.get_mut(&poi.container) {
+            container.poi.insert(poi.name.clone(), poi.clone());
+        }
+    }
 
-        for poi in json.into_values() {
-            if !containers.contains_key(&poi.container) {
-                continue;
+    // DatabaseOverride.json
+    for (name, container_override) in load_database_override() {
+        if let Some(container) = containers.get_mut(&name) {
+            if let Some(rotation_adjust) = container_override.rotation_adjust {
+                container.rotation_adjust = rotation_adjust;
+            }
+            if let Some(rotation_speed) = container_override.rotation_speed {
+                container.rotation_speed = rotation_speed;
             }
-            containers
-                .get_mut(&poi.container)
-                .unwrap()
-                .poi
-                .insert(poi.name.clone(), poi);
         }
     }
 
-    containers
+    (containers, reference_time)
+}
+
+pub fn load_settings() -> Settings {
+    if let Ok(file) = File::open("Settings.json") {
+        serde_json::from_reader(file).unwrap_or_else(|_| {
+            println!("Fail to parse Settings.json, incorrect format");
+            Settings::default()
+        })
+    } else {
+        Settings::default()
+    }
+}
+
+pub fn save_settings(settings: &Settings) {
+    let mut file = File::create("Settings.json").expect("This should work");
+    serde_json::to_writer_pretty(&mut file, settings).expect("Fail to write settings json");
+}
+
+/// Quantum drives from QtDrives.json, never empty
+pub fn load_qt_drives() -> Vec<QtDrive> {
+    let drives: Vec<QtDrive> = if let Ok(file) = File::open("QtDrives.json") {
+        serde_json::from_reader(file).unwrap_or_else(|_| {
+            println!("Fail to parse QtDrives.json, incorrect format");
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    if drives.is_empty() {
+        vec![QtDrive::default()]
+    } else {
+        drives
+    }
+}
+pub fn load_database_override() -> BTreeMap<String, ContainerOverride> {
+    if let Ok(file) = File::open("DatabaseOverride.json") {
+        serde_json::from_reader(file).unwrap_or_else(|_| {
+            println!("Fail to parse DatabaseOverride.json, incorrect format");
+            BTreeMap::new()
+        })
+    } else {
+        BTreeMap::new()
+    }
+}
+
+pub fn save_database_override(name: &str, container_override: ContainerOverride) {
+    let mut overrides = load_database_override();
+    overrides.insert(name.to_string(), container_override);
+
+    let mut file = File::create("DatabaseOverride.json").expect("This should work");
+    serde_json::to_writer_pretty(&mut file, &overrides)
+        .expect("Fail to write database override json");
 }
 
 pub fn save_history(filename: &String, position_history: &Vec<ProcessedPosition>) {
@@ -132,7 +243,18 @@ pub fn save_history(filename: &String, position_history: &Vec<ProcessedPosition>
         .unwrap_or_else(|_| panic!("Fail to write {filename}.json"))
 }
 
-pub fn import_history(filename: &String) -> Vec<ProcessedPosition> {
+pub fn save_race_report(filename: &String, report: &RaceReport) {
+    let mut file = File::create(format!("{filename}.json")).expect("This should work");
+    serde_json::to_writer_pretty(&mut file, report)
+        .unwrap_or_else(|_| panic!("Fail to write {filename}.json"))
+}
+
+pub fn save_route(filename: &String, stops: &[RouteStop]) {
+    let mut file = File::create(format!("{filename}.json")).expect("This should work");
+    serde_json::to_writer_pretty(&mut file, stops)
+        .unwrap_or_else(|_| panic!("Fail to write {filename}.json"))
+}
+pub fn load_route(filename: &String) -> Vec<RouteStop> {
     if let Ok(file) = File::open(format!("{filename}.json")) {
         serde_json::from_reader(file).unwrap_or_else(|_| {
             println!("Fail to parse {filename}.json, incorrect format");
@@ -143,51 +265,14 @@ pub fn import_history(filename: &String) -> Vec<ProcessedPosition> {
         Vec::new()
     }
 }
-
-pub fn save_to_poi(position: &ProcessedPosition) -> Poi {
-    let mut custom_pois: HashMap<String, Poi>;
-    // Open Custom Poi file
-    if let Ok(file) = fs::File::open("CustomPoi.json") {
-        custom_pois = serde_json::from_reader(file).expect("file should be proper JSON");
+pub fn import_history(filename: &String) -> Vec<ProcessedPosition> {
+    if let Ok(file) = File::open(format!("{filename}.json")) {
+        serde_json::from_reader(file).unwrap_or_else(|_| {
+            println!("Fail to parse {filename}.json, incorrect format");
+            Vec::new()
+        })
     } else {
-        println!("No file");
-        custom_pois = HashMap::new();
-    };
-
-    // Search for existing Poi with this name
-    if custom_pois.contains_key(&position.name) {
-        println!("Poi already exist, default override")
+        println!("Fail to open {filename}.json, no file");
+        Vec::new()
     }
-
-    let new_poi = if (position.container_name == "Space") | (position.container_name.is_empty()) {
-        Poi {
-            name: position.name.clone(),
-            container: "Space".to_string(),
-            coordinates: position.space_time_position.coordinates,
-            quaternions: None,
-            marker: None,
-            latitude: Some(position.latitude),
-            longitude: Some(position.longitude),
-            altitude: Some(position.altitude),
-        }
-    } else {
-        Poi {
-            name: position.name.clone(),
-            container: position.container_name.clone(),
-            coordinates: position.local_coordinates,
-            quaternions: None,
-            marker: None,
-            latitude: Some(position.latitude),
-            longitude: Some(position.longitude),
-            altitude: Some(position.altitude),
-        }
-    };
-    // Add to set
-    custom_pois.insert(position.name.clone(), new_poi.clone());
-
-    // Write files
-    let mut file = std::fs::File::create("CustomPoi.json").expect("This should work");
-    serde_json::to_writer_pretty(&mut file, &custom_pois).expect("Fail to write cutom poi json");
-
-    new_poi
 }
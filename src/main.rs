@@ -46,7 +46,7 @@ use once_cell::sync::Lazy;
 // Coordinates: x:-18930679393.98 y:-2610297380.75 z:210614.307494
 // Coordinates: x:-18930779393.98 y:-2610297380.75 z:210614.307494
 
-/// Used to compute elpased time since reference time
+/// Default reference time to compute elapsed time, if Database.json has no "Reference Time"
 static REFERENCE_TIME: Lazy<DateTime<Utc>> =
     Lazy::new(|| Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap());
 